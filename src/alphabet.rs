@@ -0,0 +1,318 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::protein::ProteinAlphabet;
+
+/// Symbols of a chaos game and the vertices they move towards --------------
+///
+/// Vertices lie on a regular polygon filling the CGR square, counterclockwise
+/// from the top right one, so that four vertices are the corners of the
+/// square. Each vertex stands for a group of symbols, written on the command
+/// line and in BICGR headers as comma-separated groups: the nucleotide
+/// alphabet is `A,T,C,G`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Alphabet {
+    /// Symbols of each vertex with its coordinates
+    vertices: Vec<(String, (f64, f64))>,
+
+    /// Fraction of the way to a vertex moved at each step
+    ratio: f64,
+}
+
+impl Alphabet {
+    /// Creates an alphabet with a vertex per group of symbols.
+    ///
+    /// Symbols are upper-cased and may only belong to one group, and there
+    /// must be at least three groups.
+    pub fn new<S: AsRef<str>>(groups: &[S]) -> Result<Self, String> {
+        if groups.len() < 3 {
+            return Err(String::from(
+                "An alphabet needs at least 3 groups of symbols",
+            ));
+        }
+        let groups: Vec<String> = groups
+            .iter()
+            .map(|g| g.as_ref().trim().to_ascii_uppercase())
+            .collect();
+        let mut seen = Vec::new();
+        for group in &groups {
+            if group.is_empty() {
+                return Err(String::from("Alphabet groups cannot be empty"));
+            }
+            for symbol in group.bytes() {
+                if !symbol.is_ascii_graphic() || symbol == b',' {
+                    return Err(format!("Invalid alphabet symbol {:?}", symbol as char));
+                }
+                if seen.contains(&symbol) {
+                    return Err(format!("Symbol {} is in several groups", symbol as char));
+                }
+                seen.push(symbol);
+            }
+        }
+
+        let n = groups.len();
+        Ok(Alphabet {
+            vertices: groups.into_iter().zip(polygon(n)).collect(),
+            ratio: contraction(n),
+        })
+    }
+
+    /// The four nucleotides on the corners of the CGR square
+    pub fn nucleotides() -> Self {
+        Alphabet::new(&["A", "T", "C", "G"]).expect("valid alphabet")
+    }
+
+    /// The nucleotides of DNA and RNA, U sharing the corner of T
+    pub fn nucleic_acids() -> Self {
+        Alphabet::nucleotides()
+            .with_uracil()
+            .expect("valid alphabet")
+    }
+
+    /// A regular polygon with a vertex per group of amino acids
+    pub fn protein(alphabet: ProteinAlphabet) -> Self {
+        Alphabet::new(alphabet.groups()).expect("valid alphabet")
+    }
+
+    /// Symbols of each vertex with its coordinates
+    pub fn vertices(&self) -> &[(String, (f64, f64))] {
+        &self.vertices
+    }
+
+    /// Fraction of the way to a vertex moved at each step
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    /// Whether the vertices are the corners of the CGR square, on which
+    /// k-mer cells and iCGR integers are defined
+    pub fn is_square(&self) -> bool {
+        self.vertices.len() == 4
+    }
+
+    /// The same alphabet with U added to the group of T, so that RNA
+    /// sequences play the same game as DNA ones
+    pub fn with_uracil(&self) -> Result<Self, String> {
+        if self.vertex(b'U').is_some() {
+            return Ok(self.clone());
+        }
+        let mut alphabet = self.clone();
+        let (symbols, _) = alphabet
+            .vertices
+            .iter_mut()
+            .find(|(symbols, _)| symbols.contains('T'))
+            .ok_or_else(|| format!("RNA needs a vertex for T in the alphabet {self}"))?;
+        symbols.push('U');
+        Ok(alphabet)
+    }
+
+    /// Vertex of a symbol
+    pub fn vertex(&self, symbol: u8) -> Option<(f64, f64)> {
+        self.vertices
+            .iter()
+            .find(|(symbols, _)| symbols.as_bytes().contains(&symbol))
+            .map(|&(_, vertex)| vertex)
+    }
+
+    /// Corner of a symbol as integer coordinates, for square alphabets
+    pub fn corner(&self, symbol: u8) -> Option<(i128, i128)> {
+        self.vertex(symbol)
+            .map(|(x, y)| (x.signum() as i128, y.signum() as i128))
+    }
+
+    /// First symbol of the corner with the given signs, for square alphabets
+    pub fn symbol_at(&self, corner: (i128, i128)) -> Option<u8> {
+        self.vertices
+            .iter()
+            .find(|(_, (x, y))| (x.signum() as i128, y.signum() as i128) == corner)
+            .map(|(symbols, _)| symbols.as_bytes()[0])
+    }
+}
+
+impl fmt::Display for Alphabet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let groups: Vec<&str> = self.vertices.iter().map(|(s, _)| s.as_str()).collect();
+        write!(f, "{}", groups.join(","))
+    }
+}
+
+impl FromStr for Alphabet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Alphabet::new(&s.split(',').collect::<Vec<_>>())
+    }
+}
+
+/// Parses an alphabet written as comma-separated groups of symbols
+pub fn parse_alphabet(s: &str) -> Result<Alphabet, String> {
+    s.parse()
+}
+
+/// Vertices of a regular polygon with `n` sides filling the CGR square,
+/// counterclockwise from the top right vertex.
+///
+/// The polygon has a flat top so that a square has the usual CGR corners.
+pub fn polygon(n: usize) -> Vec<(f64, f64)> {
+    let vertices: Vec<(f64, f64)> = (0..n)
+        .map(|i| {
+            // A quarter turn, minus half a side, plus i sides
+            let (sin, cos) = sin_cos_turns(n as u64 + 4 * i as u64 - 2, 4 * n as u64);
+            (cos, sin)
+        })
+        .collect();
+    let extent = vertices
+        .iter()
+        .fold(0.0f64, |m, &(x, y)| m.max(x.abs()).max(y.abs()));
+    vertices
+        .into_iter()
+        .map(|(x, y)| {
+            // Snap rounding errors so that square corners are exactly ±1
+            let snap = |c: f64| {
+                let c = c / extent;
+                if (c.abs() - 1.0).abs() < 1e-12 {
+                    c.signum()
+                } else {
+                    c
+                }
+            };
+            (snap(x), snap(y))
+        })
+        .collect()
+}
+
+/// Fraction of the way to a vertex moved at each step of the chaos game on a
+/// polygon with `n` sides, chosen so that the sub-polygons of the attractor
+/// touch without overlapping.
+///
+/// This is one half for triangles and squares, and grows towards one as the
+/// polygon gets more sides.
+pub fn contraction(n: usize) -> f64 {
+    let n = n as u64;
+    let (side, _) = sin_cos_turns(1, 2 * n);
+    let (opposite, _) = sin_cos_turns(1 + 2 * (n / 4), 2 * n);
+    1.0 - side / (side + opposite)
+}
+
+/// Sine and cosine of an angle of `numerator / denominator` turns.
+///
+/// The angle is reduced to a quarter turn with integers, then the Taylor
+/// series are summed with additions, multiplications and divisions only,
+/// which IEEE 754 rounds exactly, so that polygon vertices, and the images
+/// drawn from them, do not depend on the math library of the platform.
+fn sin_cos_turns(numerator: u64, denominator: u64) -> (f64, f64) {
+    let quarters = 4 * (numerator % denominator);
+    let (quadrant, rest) = (quarters / denominator, quarters % denominator);
+    let x = PI / 2.0 * rest as f64 / denominator as f64;
+
+    // Nested forms of the series, exact to rounding for x < π/2
+    let x2 = x * x;
+    let (mut sin, mut cos) = (1.0, 1.0);
+    for k in (1..=12).rev() {
+        let k = f64::from(k);
+        sin = 1.0 - x2 / ((2.0 * k) * (2.0 * k + 1.0)) * sin;
+        cos = 1.0 - x2 / ((2.0 * k - 1.0) * (2.0 * k)) * cos;
+    }
+    let sin = x * sin;
+    match quadrant {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nucleotides() {
+        let dna = Alphabet::nucleotides();
+        assert_eq!(dna.vertex(b'A'), Some((1.0, 1.0)));
+        assert_eq!(dna.vertex(b'T'), Some((-1.0, 1.0)));
+        assert_eq!(dna.vertex(b'C'), Some((-1.0, -1.0)));
+        assert_eq!(dna.vertex(b'G'), Some((1.0, -1.0)));
+        assert_eq!(dna.vertex(b'N'), None);
+        assert_eq!(dna.ratio(), 0.5);
+        assert_eq!(dna.corner(b'G'), Some((1, -1)));
+        assert_eq!(dna.symbol_at((-1, 1)), Some(b'T'));
+        assert_eq!(dna.to_string(), "A,T,C,G");
+    }
+
+    #[test]
+    fn test_with_uracil() {
+        let rna = Alphabet::nucleic_acids();
+        assert_eq!(rna.to_string(), "A,TU,C,G");
+        assert_eq!(rna.vertex(b'U'), rna.vertex(b'T'));
+        assert_eq!(rna.symbol_at((-1, 1)), Some(b'T'));
+        assert_eq!(rna.with_uracil(), Ok(rna.clone()));
+
+        let swapped = parse_alphabet("T,A,C,G").unwrap().with_uracil().unwrap();
+        assert_eq!(swapped.vertex(b'U'), Some((1.0, 1.0)));
+        assert!(parse_alphabet("A,C,G").unwrap().with_uracil().is_err());
+    }
+
+    #[test]
+    fn test_parse_alphabet() {
+        let swapped = parse_alphabet("t,a,c,g").unwrap();
+        assert!(swapped.is_square());
+        assert_eq!(swapped.vertex(b'T'), Some((1.0, 1.0)));
+        assert_eq!(swapped.to_string(), "T,A,C,G");
+
+        let purines = parse_alphabet("AG,C,T").unwrap();
+        assert_eq!(purines.vertex(b'A'), purines.vertex(b'G'));
+        assert!(!purines.is_square());
+
+        assert!(parse_alphabet("A,T").is_err());
+        assert!(parse_alphabet("A,T,,G").is_err());
+        assert!(parse_alphabet("A,T,A,G").is_err());
+    }
+
+    #[test]
+    fn test_polygon() {
+        let square = polygon(4);
+        assert_eq!(
+            square,
+            vec![(1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)]
+        );
+        let icosagon = polygon(20);
+        assert_eq!(icosagon.len(), 20);
+        assert!(icosagon
+            .iter()
+            .all(|&(x, y)| x.abs() <= 1.0 && y.abs() <= 1.0));
+    }
+
+    #[test]
+    fn test_sin_cos_turns() {
+        for (numerator, denominator) in [(0, 1), (1, 8), (1, 12), (5, 7), (3, 4), (13, 40)] {
+            let angle = 2.0 * PI * numerator as f64 / denominator as f64;
+            let (sin, cos) = sin_cos_turns(numerator, denominator);
+            assert!(
+                (sin - angle.sin()).abs() < 1e-15,
+                "{numerator}/{denominator}"
+            );
+            assert!(
+                (cos - angle.cos()).abs() < 1e-15,
+                "{numerator}/{denominator}"
+            );
+        }
+        assert_eq!(sin_cos_turns(1, 4), (1.0, 0.0));
+    }
+
+    #[test]
+    fn test_contraction() {
+        assert!((contraction(3) - 0.5).abs() < 1e-12);
+        assert!((contraction(4) - 0.5).abs() < 1e-12);
+        assert!((contraction(6) - 2.0 / 3.0).abs() < 1e-12);
+        assert!(contraction(20) > contraction(10));
+    }
+}
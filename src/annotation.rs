@@ -0,0 +1,246 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::{bail, Context};
+
+/// Annotated interval of a sequence
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feature {
+    /// ID of the annotated sequence
+    pub seqid: String,
+
+    /// 0-based start position
+    pub start: usize,
+
+    /// 0-based end position, exclusive
+    pub end: usize,
+
+    /// Feature type, e.g. CDS or tRNA
+    pub kind: String,
+}
+
+/// Reads features from a BED or a GFF file, the format being chosen from the
+/// file extension (.gff, .gff3 or .gtf for GFF, anything else for BED).
+///
+/// GFF features are named after their type, and reading stops at the
+/// `##FASTA` directive that starts embedded sequences. The fourth BED column
+/// is a feature name rather than a type, so BED features are all of type
+/// `feature`.
+pub fn read_features<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<Feature>> {
+    let path = path.as_ref();
+    let gff = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .is_some_and(|e| matches!(e.as_str(), "gff" | "gff3" | "gtf"));
+    let reader = BufReader::new(
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+    );
+
+    let mut features = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if gff && line.starts_with("##FASTA") {
+            break;
+        }
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let feature = if gff {
+            parse_gff_line(&line)
+        } else {
+            parse_bed_line(&line)
+        };
+        features.push(feature.with_context(|| {
+            format!(
+                "Error parsing feature at line {} of {}",
+                i + 1,
+                path.display()
+            )
+        })?);
+    }
+    Ok(features)
+}
+
+/// Type of the features read from BED files
+const BED_KIND: &str = "feature";
+
+fn parse_bed_line(line: &str) -> anyhow::Result<Feature> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 3 {
+        bail!("Expected at least 3 tab-separated columns");
+    }
+    let start: usize = fields[1].parse().context("Invalid start position")?;
+    let end: usize = fields[2].parse().context("Invalid end position")?;
+    if end < start {
+        bail!("End position is before start position");
+    }
+    Ok(Feature {
+        seqid: fields[0].to_string(),
+        start,
+        end,
+        kind: BED_KIND.to_string(),
+    })
+}
+
+fn parse_gff_line(line: &str) -> anyhow::Result<Feature> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 9 {
+        bail!("Expected 9 tab-separated columns");
+    }
+    // GFF positions are 1-based and inclusive
+    let start: usize = fields[3].parse().context("Invalid start position")?;
+    let end: usize = fields[4].parse().context("Invalid end position")?;
+    if start == 0 || end < start {
+        bail!("Invalid feature interval");
+    }
+    Ok(Feature {
+        seqid: fields[0].to_string(),
+        start: start - 1,
+        end,
+        kind: fields[2].to_string(),
+    })
+}
+
+/// Sequence interval given on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    /// Sequence the region applies to, every sequence when absent
+    pub seqid: Option<String>,
+
+    /// 0-based start position
+    pub start: usize,
+
+    /// 0-based end position, exclusive
+    pub end: usize,
+}
+
+impl Region {
+    /// Whether a position of a sequence lies in the region
+    pub fn contains(&self, seqid: &str, position: usize) -> bool {
+        self.seqid.as_deref().is_none_or(|id| id == seqid)
+            && (self.start..self.end).contains(&position)
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(id) = &self.seqid {
+            write!(f, "{id}:")?;
+        }
+        write!(f, "{}-{}", self.start + 1, self.end)
+    }
+}
+
+/// Parses a region written `[ID:]START-END`, with 1-based inclusive positions
+pub fn parse_region(s: &str) -> Result<Region, String> {
+    let (seqid, range) = match s.rsplit_once(':') {
+        Some((id, range)) => (Some(id.to_string()), range),
+        None => (None, s),
+    };
+    let (start, end) = range
+        .split_once('-')
+        .ok_or_else(|| format!("{s} is not a region: expected [ID:]START-END"))?;
+    let start: usize = start
+        .replace(',', "")
+        .parse()
+        .map_err(|_| format!("Invalid region start: {start}"))?;
+    let end: usize = end
+        .replace(',', "")
+        .parse()
+        .map_err(|_| format!("Invalid region end: {end}"))?;
+    if start == 0 || end < start {
+        return Err(format!(
+            "Invalid region {s}: positions start at 1 and END >= START"
+        ));
+    }
+    Ok(Region {
+        seqid,
+        start: start - 1,
+        end,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_bed_and_gff() {
+        let dir = tempdir().unwrap();
+        let bed = dir.path().join("features.bed");
+        std::fs::write(&bed, "track name=genes\nchr1\t0\t10\tCDS\nchr1\t20\t30\n").unwrap();
+        assert_eq!(
+            read_features(&bed).unwrap(),
+            vec![
+                Feature {
+                    seqid: "chr1".to_string(),
+                    start: 0,
+                    end: 10,
+                    kind: "feature".to_string()
+                },
+                Feature {
+                    seqid: "chr1".to_string(),
+                    start: 20,
+                    end: 30,
+                    kind: "feature".to_string()
+                }
+            ]
+        );
+
+        let gff = dir.path().join("features.gff3");
+        std::fs::write(
+            &gff,
+            "##gff-version 3\nchr1\tsrc\ttRNA\t5\t12\t.\t+\t.\tID=t1\n##FASTA\n>chr1\nACGT\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_features(&gff).unwrap(),
+            vec![Feature {
+                seqid: "chr1".to_string(),
+                start: 4,
+                end: 12,
+                kind: "tRNA".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_read_features_errors() {
+        let dir = tempdir().unwrap();
+        let bed = dir.path().join("bad.bed");
+        std::fs::write(&bed, "chr1\t10\t5\n").unwrap();
+        let err = read_features(&bed).unwrap_err();
+        assert!(err.to_string().contains("line 1"));
+    }
+
+    #[test]
+    fn test_parse_region() {
+        assert_eq!(
+            parse_region("chr2:1,001-2000"),
+            Ok(Region {
+                seqid: Some("chr2".to_string()),
+                start: 1000,
+                end: 2000
+            })
+        );
+        let region = parse_region("5-6").unwrap();
+        assert_eq!(region.to_string(), "5-6");
+        assert!(region.contains("any", 4) && region.contains("any", 5));
+        assert!(!region.contains("any", 6));
+        assert!(parse_region("0-5").is_err());
+        assert!(parse_region("10-5").is_err());
+        assert!(parse_region("chr1").is_err());
+    }
+}
@@ -3,9 +3,13 @@
 // This file may not be copied, modified, or distributed except according
 // to those terms.
 
-use crate::icgr::TriIntegersList;
+use crate::alphabet::Alphabet;
+use crate::error::IcgrError;
+use crate::icgr::{ChaosDecoder, TriIntegers, TriIntegersList};
 use serde::Deserialize;
+use std::fmt;
 use std::io::{self, BufRead, Write};
+use std::str::FromStr;
 
 /// Block-based Integer Chaos Game Representation (BICGR) File Format
 ///
@@ -14,7 +18,8 @@ use std::io::{self, BufRead, Write};
 ///
 /// ### BNF Grammar of BICGR file:
 /// ```text
-/// <bicgr_file>    ::= <sequence_line>+
+/// <bicgr_file>    ::= <header_line>? <sequence_line>+
+/// <header_line>   ::= "#bicgr" ("\t" <key> "=" <value>)* "\n"
 /// <sequence_line> ::= <seq_id> "\t" <description> "\t" <block_width> "\t" <overlap> "\t" <tri_integers> "\n"
 ///
 /// <seq_id>         ::= [^\t\n]+
@@ -25,6 +30,155 @@ use std::io::{self, BufRead, Write};
 /// <tri_integer>    ::= [0-9]+ "," [0-9]+ ("," [0-9])*
 /// ```
 ///
+/// The optional header records how the sequences were encoded, e.g.
+/// `alphabet=T,A,G,C` for a non-default order of the corners and
+/// `molecule=rna` for RNA sequences. Files without a header hold DNA encoded
+/// with the nucleotide alphabet `A,T,C,G`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    /// Symbols of the corners used by the encoder.
+    pub(crate) alphabet: Alphabet,
+
+    /// Kind of nucleic acid of the encoded sequences.
+    pub(crate) molecule: Molecule,
+}
+
+/// Kind of nucleic acid of the sequences of a BICGR file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Molecule {
+    #[default]
+    Dna,
+
+    /// U takes the corner of T, and is written back on decoding.
+    Rna,
+}
+
+impl fmt::Display for Molecule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Molecule::Dna => "dna",
+            Molecule::Rna => "rna",
+        })
+    }
+}
+
+impl FromStr for Molecule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dna" => Ok(Molecule::Dna),
+            "rna" => Ok(Molecule::Rna),
+            _ => Err(format!("unknown molecule {s}")),
+        }
+    }
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Header {
+            alphabet: Alphabet::nucleotides(),
+            molecule: Molecule::Dna,
+        }
+    }
+}
+
+impl Header {
+    /// Alphabet with which sequences are encoded, U sharing the corner of T
+    /// for RNA.
+    pub fn encoding_alphabet(&self) -> Result<Alphabet, String> {
+        match self.molecule {
+            Molecule::Dna => Ok(self.alphabet.clone()),
+            Molecule::Rna => self.alphabet.with_uracil(),
+        }
+    }
+
+    /// Symbol written for a decoded symbol, U instead of T for RNA.
+    fn written(&self, symbol: u8) -> u8 {
+        match (self.molecule, symbol) {
+            (Molecule::Rna, b'T') => b'U',
+            _ => symbol,
+        }
+    }
+
+    /// Symbol written for a corner.
+    pub fn symbol_at(&self, corner: (i128, i128)) -> u8 {
+        self.written(self.alphabet.symbol_at(corner).unwrap_or(b'N'))
+    }
+
+    /// Decodes a single block of a record.
+    pub fn decode_block(&self, block: &TriIntegers) -> Vec<u8> {
+        block
+            .to_dna(&self.alphabet)
+            .into_iter()
+            .map(|symbol| self.written(symbol))
+            .collect()
+    }
+
+    /// Decodes the sequence of a record, writing U instead of T for RNA.
+    pub fn decode(&self, record: &Record) -> Result<String, IcgrError> {
+        let sequence = record.tri_integers.decode(record.overlap, &self.alphabet)?;
+        Ok(match self.molecule {
+            Molecule::Dna => sequence,
+            Molecule::Rna => sequence.replace('T', "U"),
+        })
+    }
+
+    /// Writes the header line, or nothing when every field has its default
+    /// value so that such files stay readable by older versions.
+    pub fn write_all<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        if *self == Header::default() {
+            return Ok(());
+        }
+        write!(writer, "#bicgr\talphabet={}", self.alphabet)?;
+        if self.molecule != Molecule::Dna {
+            write!(writer, "\tmolecule={}", self.molecule)?;
+        }
+        writeln!(writer)
+    }
+}
+
+/// Reads the optional header line of a BICGR file, leaving the reader at the
+/// first record.
+///
+/// Only a first field of `#bicgr` makes a header line, so that the first
+/// record of a headerless file may have an ID starting with `#`.
+///
+/// # Errors
+/// Returns an `io::Error` if the header has an unknown key or an invalid value.
+pub fn read_header<R: BufRead>(reader: &mut R) -> io::Result<Header> {
+    let mut header = Header::default();
+    let buffer = reader.fill_buf()?;
+    let is_header = buffer
+        .strip_prefix(b"#bicgr")
+        .is_some_and(|rest| matches!(rest.first(), None | Some(b'\t' | b'\r' | b'\n')));
+    if !is_header {
+        return Ok(header);
+    }
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    // Skip the #bicgr field
+    let fields = line.trim_end_matches(['\r', '\n']).split('\t').skip(1);
+    for field in fields {
+        match field.split_once('=') {
+            Some(("alphabet", value)) => {
+                header.alphabet = value
+                    .parse()
+                    .map_err(|e| invalid(format!("Invalid alphabet in BICGR header: {e}")))?;
+            }
+            Some(("molecule", value)) => {
+                header.molecule = value
+                    .parse()
+                    .map_err(|e| invalid(format!("Invalid BICGR header: {e}")))?;
+            }
+            _ => return Err(invalid(format!("Unknown BICGR header field: {field}"))),
+        }
+    }
+    Ok(header)
+}
+
 /// A single BICGR record representing one encoded sequence.
 #[derive(Debug, Deserialize)]
 pub struct Record {
@@ -162,6 +316,84 @@ mod tests {
         assert!(err.to_string().contains("parsing record"));
     }
 
+    #[test]
+    fn test_header_round_trip() {
+        let header = Header {
+            alphabet: "T,A,G,C".parse().unwrap(),
+            molecule: Molecule::Dna,
+        };
+        let mut output = Vec::new();
+        header.write_all(&mut output).unwrap();
+        assert_eq!(output, b"#bicgr\talphabet=T,A,G,C\n");
+
+        output.extend_from_slice(b"seq1\t\t1\t1,2,3\n");
+        let mut reader = make_input(std::str::from_utf8(&output).unwrap());
+        assert_eq!(read_header(&mut reader).unwrap(), header);
+        let records = read_from(reader).unwrap();
+        assert_eq!(records[0].seq_id, "seq1");
+    }
+
+    #[test]
+    fn test_rna_round_trip() {
+        use crate::icgr::ChaosEncoder;
+
+        let header = Header {
+            molecule: Molecule::Rna,
+            ..Header::default()
+        };
+        let mut output = Vec::new();
+        header.write_all(&mut output).unwrap();
+        assert_eq!(output, b"#bicgr\talphabet=A,T,C,G\tmolecule=rna\n");
+        let read = read_header(&mut make_input(std::str::from_utf8(&output).unwrap())).unwrap();
+        assert_eq!(read, header);
+
+        let alphabet = header.encoding_alphabet().unwrap();
+        let rna = b"ACGUUGCAUGAC";
+        let record = Record {
+            seq_id: "rna1".to_string(),
+            desc: None,
+            overlap: 2,
+            tri_integers: rna.encode(5, 2, false, &alphabet).unwrap(),
+        };
+        let dna = b"ACGTTGCATGAC".encode(5, 2, false, &alphabet).unwrap();
+        // U and T share a corner, so RNA and DNA give the same integers
+        assert_eq!(dna, record.tri_integers);
+        assert_eq!(header.decode(&record).unwrap().as_bytes(), &rna[..]);
+        assert!(b"ACGU"
+            .encode(5, 2, true, &Alphabet::nucleotides())
+            .is_err());
+    }
+
+    #[test]
+    fn test_default_header() {
+        let mut output = Vec::new();
+        Header::default().write_all(&mut output).unwrap();
+        assert!(output.is_empty());
+
+        let mut reader = make_input("seq1\t\t1\t1,2,3\n");
+        assert_eq!(read_header(&mut reader).unwrap(), Header::default());
+        assert_eq!(read_from(reader).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_legacy_record_id_with_hash() {
+        for input in ["#seq1\t\t1\t1,2,3\n", "#bicgr1\t\t1\t1,2,3\n"] {
+            let mut reader = make_input(input);
+            assert_eq!(read_header(&mut reader).unwrap(), Header::default());
+            let records = read_from(reader).unwrap();
+            assert_eq!(records[0].seq_id, input.split('\t').next().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let mut reader = make_input("#bicgr\talphabet=A,T\n");
+        assert!(read_header(&mut reader).is_err());
+        let mut reader = make_input("#bicgr\tcolour=red\n");
+        let err = read_header(&mut reader).unwrap_err();
+        assert!(err.to_string().contains("Unknown BICGR header field"));
+    }
+
     #[test]
     fn test_write_all() {
         let record = Record {
@@ -3,18 +3,29 @@
 // This file may not be copied, modified, or distributed except according
 // to those terms.
 
+use std::collections::HashMap;
 use std::fmt;
 use std::fs::File;
 use std::io::{self, BufReader};
 use std::path::{Path, PathBuf};
-use std::process;
 use std::str;
 
+use clap::{Args, ValueEnum};
+use dssim_core::DssimImage;
+use itertools::Itertools;
 use noodles::fasta;
+use plotters::coord::Shift;
 use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
 use serde::{Deserialize, Serialize};
 use tempfile::tempdir;
 
+use crate::alphabet::{self, Alphabet};
+use crate::annotation::{self, Feature, Region};
+use crate::pdf::PdfBackend;
+use crate::protein::{self, ProteinAlphabet};
+use crate::raster;
+use crate::render::{self, ColourMap, DrawMode, Scale};
 use crate::utils;
 
 /// The Chaos Game Representation Format --------------------------------------
@@ -25,80 +36,887 @@ pub struct Chaos {
 
     /// A vector of CGR for drawing and comparison
     cgrs: Vec<(f64, f64)>,
+
+    /// 0-based position in its record of the nucleotide of each CGR point
+    positions: Vec<usize>,
+
+    /// Records the points come from, in order
+    segments: Vec<Segment>,
+
+    /// Alphabet the chaos game was played with
+    alphabet: Alphabet,
+}
+
+/// Run of CGR points coming from one record
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct Segment {
+    /// Record ID
+    id: String,
+
+    /// Index of the first point of the record
+    start: usize,
+
+    /// Length of the record sequence
+    length: usize,
+}
+
+/// Points of a CGR in drawing order with their colour, and legend entries
+#[derive(Debug, Clone, PartialEq)]
+struct Colouring {
+    points: Vec<((f64, f64), RGBColor)>,
+    legend: Vec<(String, RGBColor)>,
+}
+
+/// CGR image rendering options
+#[derive(Args, Debug, Clone, PartialEq)]
+pub struct DrawOptions {
+    /// Image width in pixels, the image is square unless a height is given
+    #[arg(long, default_value_t = 1024, value_name = "INT", value_parser = clap::value_parser!(u32).range(16..))]
+    pub size: u32,
+
+    /// Image height in pixels, defaults to the width
+    #[arg(long, value_name = "INT", value_parser = clap::value_parser!(u32).range(16..))]
+    pub height: Option<u32>,
+
+    /// Radius of the points in pixels
+    #[arg(long = "point-size", default_value_t = 2, value_name = "INT")]
+    pub point_size: u32,
+
+    /// Colour of the points, as a name or #RRGGBB
+    #[arg(long, default_value = "black", value_parser = parse_colour)]
+    pub colour: RGBColor,
+
+    /// Opacity of the points, between 0 and 1
+    #[arg(long, default_value_t = 1.0, value_name = "FLOAT", value_parser = parse_opacity)]
+    pub opacity: f64,
+
+    /// Background colour, as a name or #RRGGBB
+    #[arg(long, default_value = "white", value_parser = parse_colour)]
+    pub background: RGBColor,
+
+    /// Hide the axis labels
+    #[arg(long = "no-axes", action = clap::ArgAction::SetTrue)]
+    pub no_axes: bool,
+
+    /// Hide the mesh
+    #[arg(long = "no-mesh", action = clap::ArgAction::SetTrue)]
+    pub no_mesh: bool,
+
+    /// Label each corner with its nucleotide
+    #[arg(long = "corner-labels", action = clap::ArgAction::SetTrue)]
+    pub corner_labels: bool,
+
+    /// Draw every point, or a heatmap of the number of points per pixel
+    #[arg(long, value_enum, default_value_t = DrawMode::Points)]
+    pub mode: DrawMode,
+
+    /// Scaling of pixel counts in density mode
+    #[arg(long, value_enum, default_value_t = Scale::Log)]
+    pub scale: Scale,
+
+    /// Colour map of density mode
+    #[arg(long = "colour-map", value_enum, default_value_t = ColourMap::Viridis)]
+    pub colour_map: ColourMap,
+
+    /// Hide the colour bar of density mode
+    #[arg(long = "no-legend", action = clap::ArgAction::SetTrue)]
+    pub no_legend: bool,
+
+    /// Colour points by their position along the sequence, using the colour map
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with_all = ["features", "highlight"])]
+    pub gradient: bool,
+
+    /// BED or GFF file of features, points are coloured by GFF feature type, all
+    /// BED features taking one colour
+    #[arg(long, value_name = "FILE")]
+    pub features: Option<PathBuf>,
+
+    /// Highlight the points of a region, written [ID:]START-END (1-based)
+    #[arg(long, value_name = "REGION", value_parser = annotation::parse_region, conflicts_with = "features")]
+    pub highlight: Option<Region>,
+
+    /// Colour of highlighted points, as a name or #RRGGBB
+    #[arg(long = "highlight-colour", default_value = "red", value_parser = parse_colour)]
+    pub highlight_colour: RGBColor,
+
+    /// Overlay the grid of k-mer cells at depth K, labelled with their k-mer
+    /// when the cells are large enough
+    #[arg(long = "kmer-grid", value_name = "K", value_parser = clap::value_parser!(u8).range(1..=8))]
+    pub kmer_grid: Option<u8>,
+
+    /// Shade the cell of a motif, e.g. CG (can be repeated)
+    #[arg(long, value_name = "MOTIF", value_parser = parse_motif)]
+    pub motif: Vec<String>,
+
+    /// Colour of motif cells, as a name or #RRGGBB
+    #[arg(long = "motif-colour", default_value = "red", value_parser = parse_colour)]
+    pub motif_colour: RGBColor,
+
+    /// Resolution recorded in the image, in dots per inch
+    #[arg(long, default_value_t = 72, value_name = "INT", value_parser = clap::value_parser!(u32).range(1..))]
+    pub dpi: u32,
+
+    /// Render PNG images with the built-in rasteriser, which needs no font and
+    /// gives identical images on every platform but draws no axis labels
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub raster: bool,
+}
+
+impl Default for DrawOptions {
+    fn default() -> Self {
+        DrawOptions {
+            size: 1024,
+            height: None,
+            point_size: 2,
+            colour: BLACK,
+            opacity: 1.0,
+            background: WHITE,
+            no_axes: false,
+            no_mesh: false,
+            corner_labels: false,
+            mode: DrawMode::Points,
+            scale: Scale::Log,
+            colour_map: ColourMap::Viridis,
+            no_legend: false,
+            gradient: false,
+            features: None,
+            highlight: None,
+            highlight_colour: RED,
+            kmer_grid: None,
+            motif: Vec::new(),
+            motif_colour: RED,
+            dpi: 72,
+            raster: false,
+        }
+    }
+}
+
+impl DrawOptions {
+    /// Image width and height in pixels
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.size, self.height.unwrap_or(self.size))
+    }
+}
+
+/// Parses a colour given as a name or as an #RRGGBB hexadecimal code
+pub fn parse_colour(s: &str) -> Result<RGBColor, String> {
+    let named = match s.to_ascii_lowercase().as_str() {
+        "black" => Some(BLACK),
+        "white" => Some(WHITE),
+        "red" => Some(RED),
+        "green" => Some(GREEN),
+        "blue" => Some(BLUE),
+        "cyan" => Some(CYAN),
+        "magenta" => Some(MAGENTA),
+        "yellow" => Some(YELLOW),
+        _ => None,
+    };
+    if let Some(colour) = named {
+        return Ok(colour);
+    }
+
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("{s} is not a colour name or an #RRGGBB code"));
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .map_err(|_| format!("{s} is not a colour name or an #RRGGBB code"))
+    };
+    Ok(RGBColor(channel(0)?, channel(2)?, channel(4)?))
 }
 
+fn parse_opacity(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(v),
+        Ok(_) => Err(String::from("opacity must be between 0 and 1")),
+        Err(_) => Err(String::from("opacity must be a number")),
+    }
+}
+
+/// Parses a motif made of letters, checked against the alphabet when drawing
+fn parse_motif(s: &str) -> Result<String, String> {
+    let motif = s.to_ascii_uppercase();
+    if motif.is_empty() || motif.len() > 12 {
+        return Err(String::from("Motifs must have 1 to 12 nucleotides"));
+    }
+    if !motif.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return Err(format!(
+            "{s} should only contain letters, e.g. A, C, G and T"
+        ));
+    }
+    Ok(motif)
+}
+
+/// Width in pixels of the colour bar drawn next to density heatmaps
+pub(crate) const LEGEND_WIDTH: u32 = 100;
+
 impl Chaos {
-    /// Draws the CGR and saves it as a PNG file
-    fn draw(&self, output: Option<PathBuf>) -> anyhow::Result<()> {
+    /// Adds the points of the reverse complement of the record the CGR was
+    /// played on, each point keeping the position of its nucleotide on the
+    /// forward strand
+    fn add_reverse_complement(&mut self, record: &fasta::Record) {
+        let length = record.sequence().len();
+        let reverse = fasta::Record::new(
+            record.definition().clone(),
+            fasta::record::Sequence::from(utils::reverse_complement(record.sequence().as_ref())),
+        );
+        let strand = reverse.record_to_chaos(&self.alphabet);
+        self.cgrs.extend(strand.cgrs);
+        self.positions
+            .extend(strand.positions.into_iter().map(|p| length - 1 - p));
+    }
+
+    /// Draws the CGR and saves it as a PNG, SVG or PDF file depending on the
+    /// output extension
+    fn draw(
+        &self,
+        output: Option<PathBuf>,
+        options: &DrawOptions,
+        features: Option<&[Feature]>,
+    ) -> anyhow::Result<()> {
+        let colouring = self.colouring(options, features);
         let image = if let Some(out) = output {
             out
         } else {
-            PathBuf::from(format!("{}.png", self.id))
+            PathBuf::from(format!("{}.png", file_name_id(&self.id)))
+        };
+
+        let (width, height) = options.dimensions();
+        let extension = image
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("svg") => {
+                let root_area = SVGBackend::new(&image, (width, height)).into_drawing_area();
+                self.render(&root_area, options, &colouring)?;
+                root_area.present()?;
+            }
+            Some("pdf") => {
+                let root_area =
+                    PdfBackend::new(&image, (width, height), options.dpi).into_drawing_area();
+                self.render(&root_area, options, &colouring)?;
+                root_area.present()?;
+            }
+            // Without fonts, bitmaps cannot show text
+            _ if options.raster || cfg!(not(feature = "fonts")) => {
+                let canvas = raster::render(&colouring.points, options, &self.alphabet)?;
+                render::save_png(&image, canvas.pixels(), width, height, options.dpi)?;
+            }
+            _ => {
+                let mut buffer = vec![0u8; width as usize * height as usize * 3];
+                {
+                    let root_area = BitMapBackend::with_buffer(&mut buffer, (width, height))
+                        .into_drawing_area();
+                    self.render(&root_area, options, &colouring)?;
+                    root_area.present()?;
+                }
+                render::save_png(&image, &buffer, width, height, options.dpi)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Colours the points as requested by the options
+    fn colouring(&self, options: &DrawOptions, features: Option<&[Feature]>) -> Colouring {
+        // Record and position of each point
+        let located = self.segments.iter().enumerate().flat_map(|(s, segment)| {
+            let end = self
+                .segments
+                .get(s + 1)
+                .map_or(self.cgrs.len(), |next| next.start);
+            (segment.start..end).map(move |i| (segment, self.positions[i]))
+        });
+
+        let mut legend = Vec::new();
+        // Colour and drawing layer of each point, higher layers being drawn on top
+        let styles: Vec<(RGBColor, usize)> = if options.gradient {
+            located
+                .map(|(segment, position)| {
+                    let value = position as f64 / segment.length.saturating_sub(1).max(1) as f64;
+                    (options.colour_map.colour(value), 0)
+                })
+                .collect()
+        } else if let Some(features) = features {
+            let mut kinds: Vec<&str> = Vec::new();
+            let mut by_seqid: HashMap<&str, Vec<(usize, &Feature)>> = HashMap::new();
+            for feature in features {
+                let kind = match kinds.iter().position(|k| *k == feature.kind) {
+                    Some(kind) => kind,
+                    None => {
+                        kinds.push(&feature.kind);
+                        kinds.len() - 1
+                    }
+                };
+                by_seqid
+                    .entry(&feature.seqid)
+                    .or_default()
+                    .push((kind, feature));
+            }
+            for intervals in by_seqid.values_mut() {
+                intervals.sort_by_key(|(_, f)| f.start);
+            }
+
+            let mut styles = vec![(options.colour, 0); self.cgrs.len()];
+            for (s, segment) in self.segments.iter().enumerate() {
+                let end = self
+                    .segments
+                    .get(s + 1)
+                    .map_or(self.cgrs.len(), |next| next.start);
+                let intervals = by_seqid.get(segment.id.as_str()).map_or(&[][..], |v| v);
+
+                // Points of the reverse strand come by decreasing positions,
+                // so the features are swept along the points sorted by position
+                let mut order: Vec<usize> = (segment.start..end).collect();
+                order.sort_by_key(|&i| self.positions[i]);
+                let (mut next, mut active) = (0, Vec::new());
+                for i in order {
+                    let position = self.positions[i];
+                    while next < intervals.len() && intervals[next].1.start <= position {
+                        active.push(intervals[next]);
+                        next += 1;
+                    }
+                    active.retain(|(_, f)| f.end > position);
+                    // Nested features such as a CDS within a gene take the innermost type
+                    if let Some(&(kind, _)) = active.iter().min_by_key(|(_, f)| f.end - f.start) {
+                        styles[i] = (render::category_colour(kind), 1);
+                    }
+                }
+            }
+
+            legend.extend(
+                kinds
+                    .iter()
+                    .enumerate()
+                    .map(|(i, kind)| (kind.to_string(), render::category_colour(i))),
+            );
+            legend.push((String::from("unannotated"), options.colour));
+            styles
+        } else if let Some(region) = &options.highlight {
+            legend.push((region.to_string(), options.highlight_colour));
+            located
+                .map(|(segment, position)| {
+                    if region.contains(&segment.id, position) {
+                        (options.highlight_colour, 1)
+                    } else {
+                        (options.colour, 0)
+                    }
+                })
+                .collect()
+        } else {
+            vec![(options.colour, 0); self.cgrs.len()]
         };
 
-        let root_area = BitMapBackend::new(&image, (1024, 768)).into_drawing_area();
-        root_area.fill(&WHITE)?;
+        let mut points: Vec<((f64, f64), RGBColor, usize)> = self
+            .cgrs
+            .iter()
+            .zip(styles)
+            .map(|(&point, (colour, layer))| (point, colour, layer))
+            .collect();
+        points.sort_by_key(|&(_, _, layer)| layer);
+
+        Colouring {
+            points: points
+                .into_iter()
+                .map(|(point, colour, _)| (point, colour))
+                .collect(),
+            legend,
+        }
+    }
+
+    /// Renders the CGR on a drawing area
+    fn render<DB>(
+        &self,
+        root_area: &DrawingArea<DB, Shift>,
+        options: &DrawOptions,
+        colouring: &Colouring,
+    ) -> anyhow::Result<()>
+    where
+        DB: DrawingBackend,
+        DB::ErrorType: 'static,
+    {
+        root_area.fill(&options.background)?;
+        let (width, height) = root_area.dim_in_pixel();
+
+        let legend = options.mode == DrawMode::Density && !options.no_legend;
+        let (chart_area, legend_area) = if legend {
+            let (chart, legend) = root_area.split_horizontally(width.saturating_sub(LEGEND_WIDTH));
+            (chart, Some(legend))
+        } else {
+            (root_area.clone(), None)
+        };
 
-        let mut ctx = ChartBuilder::on(&root_area)
-            .set_label_area_size(LabelAreaPosition::Left, 40)
-            .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        let label_area = if options.no_axes { 0 } else { 40 };
+        let mut ctx = ChartBuilder::on(&chart_area)
+            .set_label_area_size(LabelAreaPosition::Left, label_area)
+            .set_label_area_size(LabelAreaPosition::Bottom, label_area)
             .build_cartesian_2d(-1f64..1f64, -1f64..1f64)?;
-        ctx.configure_mesh().draw()?;
+        if !(options.no_axes && options.no_mesh) {
+            let mut mesh = ctx.configure_mesh();
+            if options.no_mesh {
+                mesh.disable_mesh();
+            }
+            mesh.draw()?;
+        }
+
+        match options.mode {
+            DrawMode::Points => {
+                ctx.draw_series(colouring.points.iter().map(|&(point, colour)| {
+                    Circle::new(
+                        point,
+                        options.point_size,
+                        colour.mix(options.opacity).filled(),
+                    )
+                }))?;
+
+                if !colouring.legend.is_empty() {
+                    for (label, colour) in &colouring.legend {
+                        let colour = *colour;
+                        ctx.draw_series(std::iter::empty::<Circle<(f64, f64), u32>>())?
+                            .label(label)
+                            .legend(move |point| Circle::new(point, 4, colour.filled()));
+                    }
+                    ctx.configure_series_labels()
+                        .position(SeriesLabelPosition::UpperMiddle)
+                        .background_style(WHITE.mix(0.8))
+                        .border_style(BLACK)
+                        .draw()?;
+                }
+            }
+            DrawMode::Density => {
+                let pixels = ctx.plotting_area().strip_coord_spec();
+                let (pw, ph) = pixels.dim_in_pixel();
+                let grid = render::bin_points(&self.cgrs, pw as usize, ph as usize);
+                let max = grid.iter().copied().max().unwrap_or(0);
+
+                for (i, &count) in grid.iter().enumerate().filter(|(_, &c)| c > 0) {
+                    let colour = options
+                        .colour_map
+                        .colour(options.scale.normalise(count, max));
+                    let (x, y) = (i % pw as usize, i / pw as usize);
+                    pixels.draw_pixel((x as i32, y as i32), &colour)?;
+                }
+
+                if let Some(legend_area) = legend_area {
+                    // Align the colour bar with the plotting area
+                    let bottom = height.saturating_sub(label_area) as i32;
+                    draw_colour_bar(&legend_area, options, max, (10, bottom - 10))?;
+                }
+            }
+        }
+
+        let overlay = kmer_overlay(options, &self.alphabet);
+        let motif_style = options.motif_colour.mix(0.35).filled();
+        ctx.draw_series(overlay.motifs.iter().map(|&((x, y), half)| {
+            Rectangle::new([(x - half, y + half), (x + half, y - half)], motif_style)
+        }))?;
+        let grid_style = BLACK.mix(0.6);
+        ctx.draw_series(overlay.lines.iter().flat_map(|&at| {
+            [
+                PathElement::new(vec![(at, -1.0), (at, 1.0)], grid_style),
+                PathElement::new(vec![(-1.0, at), (1.0, at)], grid_style),
+            ]
+        }))?;
+        if let Some(k) = options.kmer_grid {
+            let (pw, ph) = ctx.plotting_area().dim_in_pixel();
+            let cell = f64::from(pw.min(ph)) / f64::from(1u32 << k);
+            // Labels take about half of the cell width
+            let font_size = (cell * 0.5 / f64::from(k)).min(cell * 0.3);
+            if font_size >= 6.0 {
+                let style = TextStyle::from(("sans-serif", font_size).into_font())
+                    .pos(Pos::new(HPos::Center, VPos::Center));
+                ctx.draw_series(
+                    overlay
+                        .labels
+                        .iter()
+                        .map(|(kmer, centre)| Text::new(kmer.clone(), *centre, style.clone())),
+                )?;
+            }
+        }
 
-        ctx.draw_series(
-            self.cgrs
+        if options.corner_labels {
+            // Labels sit inside the square on the side of their vertex, with a
+            // halo of background colour as points crowd around vertices
+            let font_size = if self.alphabet.is_square() {
+                (height / 32).max(12)
+            } else {
+                (height / 48).max(12)
+            };
+            let (pw, ph) = ctx.plotting_area().dim_in_pixel();
+            let (dx, dy) = (2.0 / f64::from(pw.max(1)), 2.0 / f64::from(ph.max(1)));
+            let side = |c: f64| i8::from(c > 0.1) - i8::from(c < -0.1);
+            let labels: Vec<(&str, (f64, f64), Pos)> = self
+                .alphabet
+                .vertices()
                 .iter()
-                .map(|&point| Circle::new(point, 2, BLACK.filled())),
-        )?;
+                .map(|(label, (x, y))| {
+                    let h = match side(*x) {
+                        -1 => HPos::Left,
+                        1 => HPos::Right,
+                        _ => HPos::Center,
+                    };
+                    let v = match side(*y) {
+                        -1 => VPos::Bottom,
+                        1 => VPos::Top,
+                        _ => VPos::Center,
+                    };
+                    (label.as_str(), (*x, *y), Pos::new(h, v))
+                })
+                .collect();
+            for (offset, colour) in (-1..=1)
+                .cartesian_product(-1..=1)
+                .map(|o| (o, options.background))
+                .chain([((0, 0), BLACK)])
+            {
+                ctx.draw_series(labels.iter().map(|&(label, (x, y), pos)| {
+                    let style = TextStyle::from(("sans-serif", font_size).into_font())
+                        .color(&colour)
+                        .pos(pos);
+                    let at = (
+                        x + 1.5 * dx * f64::from(offset.0),
+                        y + 1.5 * dy * f64::from(offset.1),
+                    );
+                    Text::new(label.to_string(), at, style)
+                }))?;
+            }
+        }
+
         Ok(())
     }
 }
 
-/// Trait for converting DNA sequences to Chaos Game Representation (CGR)
-trait DnaToChaos {
-    fn record_to_chaos(&self) -> Chaos;
+/// Draws a vertical colour bar labelled with counts, spanning rows `top..bottom`
+fn draw_colour_bar<DB>(
+    area: &DrawingArea<DB, Shift>,
+    options: &DrawOptions,
+    max: u64,
+    (top, bottom): (i32, i32),
+) -> anyhow::Result<()>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let (left, right) = (10, 30);
+    let span = (bottom - top).max(1);
+
+    for y in top..bottom {
+        let colour = options
+            .colour_map
+            .colour(f64::from(bottom - y) / f64::from(span));
+        area.draw(&Rectangle::new(
+            [(left, y), (right, y + 1)],
+            colour.filled(),
+        ))?;
+    }
+    area.draw(&Rectangle::new([(left, top), (right, bottom)], BLACK))?;
+
+    let style =
+        TextStyle::from(("sans-serif", 14).into_font()).pos(Pos::new(HPos::Left, VPos::Center));
+    for t in [0.0, 0.5, 1.0] {
+        let y = bottom - (t * f64::from(span)).round() as i32;
+        let label = format!("{:.0}", options.scale.count_at(t, max));
+        area.draw(&Text::new(label, (right + 5, y), style.clone()))?;
+    }
+
+    Ok(())
 }
 
-impl DnaToChaos for fasta::Record {
-    fn record_to_chaos(&self) -> Chaos {
-        let mut result = Vec::with_capacity(self.sequence().len());
+/// Alphabet the chaos game is played with
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CgrAlphabet {
+    /// Nucleotides or the 20 amino acids, detected from the sequences
+    Auto,
+    /// A, C, G and T on the corners of the square
+    Dna,
+    /// The 20 amino acids on the vertices of a regular polygon
+    Protein,
+    /// Amino acids grouped as in the detailed HP model, on a square
+    ProteinHp,
+    /// Amino acids in 6 Dayhoff groups, on a hexagon
+    ProteinDayhoff,
+    /// Amino acids in 10 groups of Murphy et al. (2000), on a decagon
+    ProteinMurphy10,
+}
+
+/// Alphabet options of the chaos game
+#[derive(Args, Debug, Clone, PartialEq)]
+pub struct AlphabetOptions {
+    /// Play the chaos game with nucleotides or amino acids
+    #[arg(long, value_enum, default_value_t = CgrAlphabet::Auto)]
+    pub alphabet: CgrAlphabet,
 
-        let nucleotides = [
-            (b'A', [1.0, 1.0]),
-            (b'T', [-1.0, 1.0]),
-            (b'C', [-1.0, -1.0]),
-            (b'G', [1.0, -1.0]),
-        ];
+    /// Custom alphabet as comma-separated groups of symbols, one per vertex
+    /// counterclockwise from the top right corner, e.g. T,A,G,C
+    #[arg(long, value_name = "GROUPS", value_parser = alphabet::parse_alphabet, conflicts_with = "alphabet")]
+    pub vertices: Option<Alphabet>,
+}
+
+impl Default for AlphabetOptions {
+    fn default() -> Self {
+        AlphabetOptions {
+            alphabet: CgrAlphabet::Auto,
+            vertices: None,
+        }
+    }
+}
+
+impl AlphabetOptions {
+    /// Alphabet of the records, sequences being amino acids in auto mode when
+    /// the first records look like proteins
+    fn resolve(&self, records: &[fasta::Record]) -> Alphabet {
+        if let Some(custom) = &self.vertices {
+            // As with the default alphabet, U plays as T in RNA sequences
+            // whenever the custom alphabet has a vertex for T
+            return custom.with_uracil().unwrap_or_else(|_| custom.clone());
+        }
+        match self.alphabet {
+            CgrAlphabet::Auto => {
+                let sample: Vec<u8> = records
+                    .iter()
+                    .flat_map(|r| r.sequence().as_ref().iter().copied())
+                    .take(10_000)
+                    .collect();
+                if protein::is_protein(&sample) {
+                    Alphabet::protein(ProteinAlphabet::Full)
+                } else {
+                    Alphabet::nucleic_acids()
+                }
+            }
+            CgrAlphabet::Dna => Alphabet::nucleic_acids(),
+            CgrAlphabet::Protein => Alphabet::protein(ProteinAlphabet::Full),
+            CgrAlphabet::ProteinHp => Alphabet::protein(ProteinAlphabet::Hp),
+            CgrAlphabet::ProteinDayhoff => Alphabet::protein(ProteinAlphabet::Dayhoff),
+            CgrAlphabet::ProteinMurphy10 => Alphabet::protein(ProteinAlphabet::Murphy10),
+        }
+    }
+}
+
+/// Cell of the CGR square holding the points that end with a k-mer, as its
+/// centre and half side, for square alphabets.
+///
+/// Playing the chaos game with the k-mer from the centre of the square lands
+/// on the centre of its cell, of side 2 / 2^k.
+fn kmer_cell(kmer: &[u8], alphabet: &Alphabet) -> Option<((f64, f64), f64)> {
+    let mut centre = (0.0, 0.0);
+    for &symbol in kmer {
+        let (x, y) = alphabet.vertex(symbol)?;
+        centre = (0.5 * (centre.0 + x), 0.5 * (centre.1 + y));
+    }
+    Some((centre, 0.5f64.powi(kmer.len() as i32)))
+}
+
+/// Grid and motif cells drawn over a CGR
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct KmerOverlay {
+    /// Positions of the vertical and horizontal grid lines inside the square
+    pub(crate) lines: Vec<f64>,
+
+    /// Each k-mer of the grid with the centre of its cell
+    pub(crate) labels: Vec<(String, (f64, f64))>,
+
+    /// Centre and half side of the cell of each motif
+    pub(crate) motifs: Vec<((f64, f64), f64)>,
+}
+
+/// Computes the k-mer grid and motif cells requested by the options, k-mers
+/// being written with the first symbol of each vertex
+pub(crate) fn kmer_overlay(options: &DrawOptions, alphabet: &Alphabet) -> KmerOverlay {
+    let (mut lines, mut labels) = (Vec::new(), Vec::new());
+    if let Some(k) = options.kmer_grid {
+        let cells = 1u32 << k;
+        lines = (1..cells)
+            .map(|i| -1.0 + 2.0 * f64::from(i) / f64::from(cells))
+            .collect();
+        labels = (0..k)
+            .map(|_| alphabet.vertices().iter().map(|(s, _)| s.as_bytes()[0]))
+            .multi_cartesian_product()
+            .map(|kmer| {
+                let (centre, _) = kmer_cell(&kmer, alphabet).expect("k-mers are made of symbols");
+                (String::from_utf8_lossy(&kmer).into_owned(), centre)
+            })
+            .collect();
+    }
+    let motifs = options
+        .motif
+        .iter()
+        .filter_map(|motif| kmer_cell(motif.as_bytes(), alphabet))
+        .collect();
+
+    KmerOverlay {
+        lines,
+        labels,
+        motifs,
+    }
+}
+
+/// Trait for converting sequences to Chaos Game Representation (CGR)
+trait ToChaos {
+    fn record_to_chaos(&self, alphabet: &Alphabet) -> Chaos;
+}
+
+impl ToChaos for fasta::Record {
+    fn record_to_chaos(&self, alphabet: &Alphabet) -> Chaos {
+        let mut result = Vec::with_capacity(self.sequence().len());
 
         let mut coords = (0.0, 0.0);
+        let mut positions = Vec::with_capacity(self.sequence().len());
 
-        for nucleotide in self.sequence().as_ref() {
-            if let Some(&(_, pos)) = nucleotides.iter().find(|&&(n, _)| n == *nucleotide) {
-                coords.0 = 0.5 * (coords.0 + pos[0]);
-                coords.1 = 0.5 * (coords.1 + pos[1]);
+        for (i, &symbol) in self.sequence().as_ref().iter().enumerate() {
+            if let Some(pos) = alphabet.vertex(symbol) {
+                coords.0 += alphabet.ratio() * (pos.0 - coords.0);
+                coords.1 += alphabet.ratio() * (pos.1 - coords.1);
                 result.push(coords);
+                positions.push(i);
             }
         }
 
         Chaos {
             id: self.name().to_string(),
             cgrs: result,
+            positions,
+            segments: vec![Segment {
+                id: self.name().to_string(),
+                start: 0,
+                length: self.sequence().len(),
+            }],
+            alphabet: alphabet.clone(),
         }
     }
 }
 
-/// Reads a FASTA file, generates its CGR, and saves it as an image.
-pub fn draw<R: io::Read>(source: R, destination: Option<PathBuf>) -> anyhow::Result<()> {
+/// How multi-record FASTA files are represented
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum RecordMode {
+    /// One CGR per record, named by record ID
+    PerRecord,
+    /// A single CGR gathering all records, each record restarting from the
+    /// centre of the square so that no k-mer spans two records
+    Assembly,
+}
+
+/// Reads the records of a FASTA file as CGRs, adding the points of the
+/// reverse complement of each record when `canonical`
+fn read_chaos<R: io::Read>(
+    source: R,
+    mode: RecordMode,
+    alphabet: &AlphabetOptions,
+    canonical: bool,
+) -> anyhow::Result<Vec<Chaos>> {
     let mut reader = fasta::Reader::new(BufReader::new(source));
+    let records = reader.records().collect::<io::Result<Vec<_>>>()?;
+    let alphabet = alphabet.resolve(&records);
+    if canonical && (!alphabet.is_square() || b"ACGT".iter().any(|&b| alphabet.vertex(b).is_none()))
+    {
+        anyhow::bail!("Canonical CGRs need a nucleotide alphabet, got {alphabet}");
+    }
 
-    for (count, result) in reader.records().enumerate() {
-        let record = result?;
-        if count == 0 {
-            let chaos = record.record_to_chaos();
-            chaos.draw(destination.clone())?;
-        } else {
-            anyhow::bail!("Cannot draw from multi-sequence fasta");
+    let mut chaos: Vec<Chaos> = records
+        .iter()
+        .map(|record| {
+            let mut chaos = record.record_to_chaos(&alphabet);
+            if canonical {
+                chaos.add_reverse_complement(record);
+            }
+            chaos
+        })
+        .collect();
+
+    if mode == RecordMode::Assembly && chaos.len() > 1 {
+        let mut records = chaos.into_iter();
+        let mut assembly = records.next().expect("at least two records");
+        for record in records {
+            let offset = assembly.cgrs.len();
+            assembly.cgrs.extend(record.cgrs);
+            assembly.positions.extend(record.positions);
+            assembly
+                .segments
+                .extend(record.segments.into_iter().map(|segment| Segment {
+                    start: segment.start + offset,
+                    ..segment
+                }));
         }
+        chaos = vec![assembly];
+    }
+
+    Ok(chaos)
+}
+
+/// Record ID usable in a file name on any platform: path separators and
+/// characters other than ASCII letters, digits, '.', '-' and '_' become '_'
+fn file_name_id(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Output image of a record when several records are drawn from one file
+fn record_output(destination: &Path, id: &str) -> PathBuf {
+    let stem = destination
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = destination
+        .extension()
+        .map_or_else(|| String::from("png"), |e| e.to_string_lossy().into_owned());
+    destination.with_file_name(format!("{stem}_{}.{extension}", file_name_id(id)))
+}
+
+/// Reads a FASTA file, generates its CGR, and saves it as an image.
+///
+/// In per-record mode, each record of a multi-record file is saved to its own
+/// image, suffixed with the record ID when an output file is given. Canonical
+/// CGRs also play the game on the reverse complement of each record.
+pub fn draw<R: io::Read>(
+    source: R,
+    destination: Option<PathBuf>,
+    mode: RecordMode,
+    alphabet: &AlphabetOptions,
+    canonical: bool,
+    options: &DrawOptions,
+) -> anyhow::Result<()> {
+    let chaos = read_chaos(source, mode, alphabet, canonical)?;
+    let several = chaos.len() > 1;
+    if options.mode == DrawMode::Density
+        && (options.gradient || options.features.is_some() || options.highlight.is_some())
+    {
+        anyhow::bail!("Colouring points by position, feature or region requires --mode points");
+    }
+    if (options.kmer_grid.is_some() || !options.motif.is_empty())
+        && chaos.iter().any(|c| !c.alphabet.is_square())
+    {
+        anyhow::bail!("K-mer grids and motifs are only drawn on CGRs with 4 vertices");
+    }
+    if let Some(record) = chaos.first() {
+        if let Some(motif) = options
+            .motif
+            .iter()
+            .find(|m| m.bytes().any(|b| record.alphabet.vertex(b).is_none()))
+        {
+            anyhow::bail!(
+                "Motif {motif} has symbols outside the alphabet {}",
+                record.alphabet
+            );
+        }
+    }
+    let features = options
+        .features
+        .as_ref()
+        .map(annotation::read_features)
+        .transpose()?;
+
+    for record in chaos {
+        let output = match &destination {
+            Some(out) if several => Some(record_output(out, &record.id)),
+            _ => destination.clone(),
+        };
+        record.draw(output, options, features.as_deref())?;
     }
 
     Ok(())
@@ -110,21 +928,19 @@ pub struct SSIMResult {
     query: String,
     reference: String,
     ssim: f64,
+    /// k-mer length of the FCGR images compared, if not CGR images
+    fcgr_k: Option<u8>,
 }
 
 impl SSIMResult {
-    pub fn new() -> Self {
-        Self {
-            query: String::new(),
-            reference: String::new(),
-            ssim: 0.0,
+    /// Marks the score as computed on the FCGR images of k-mers, whose
+    /// scores are not comparable with those of CGR images
+    pub(crate) fn on_fcgr(self, k: u8) -> Self {
+        SSIMResult {
+            fcgr_k: Some(k),
+            ..self
         }
     }
-    fn add(&mut self, query: String, reference: String, ssim: f64) {
-        self.query = query;
-        self.reference = reference;
-        self.ssim = ssim;
-    }
 }
 
 impl fmt::Display for SSIMResult {
@@ -141,36 +957,68 @@ impl fmt::Display for SSIMResult {
                 .unwrap()
                 .to_string_lossy(),
             self.ssim
-        )
+        )?;
+        if let Some(k) = self.fcgr_k {
+            write!(f, "\tfcgr-k{k}")?;
+        }
+        Ok(())
     }
 }
 
-/// Compares two genome sequences based on CGR images
-pub fn compare_genomes(query: &str, reference: &str) -> anyhow::Result<SSIMResult> {
+/// Draws the CGR images of a genome file and loads them back for comparison.
+///
+/// In per-record mode, each image is named after the file and the record ID.
+pub fn genome_images(
+    genome: &str,
+    mode: RecordMode,
+    alphabet: &AlphabetOptions,
+    canonical: bool,
+) -> anyhow::Result<Vec<(DssimImage<f32>, String)>> {
     // Create temporary directory
     let dir = tempdir()?;
 
-    let attr = dssim_core::Dssim::new();
-    let mut result = SSIMResult::new();
+    let chaos = read_chaos(File::open(genome)?, mode, alphabet, canonical)?;
+    if chaos.is_empty() {
+        anyhow::bail!("No sequence found in {genome}");
+    }
+    let several = chaos.len() > 1;
 
-    let qimg_out = PathBuf::from(format!("{:?}/query.png", dir.path()));
-    let rimg_out = PathBuf::from(format!("{:?}/reference.png", dir.path()));
-    draw(File::open(query)?, Some(qimg_out.clone()))?;
-    draw(File::open(reference)?, Some(rimg_out.clone()))?;
+    let mut images = Vec::with_capacity(chaos.len());
+    for (i, record) in chaos.into_iter().enumerate() {
+        let img_out = dir.path().join(format!("genome_{i}.png"));
+        let name = if several {
+            format!("{genome}:{}", record.id)
+        } else {
+            genome.to_string()
+        };
+        record.draw(Some(img_out.clone()), &DrawOptions::default(), None)?;
 
-    // Read images
-    let qimage = utils::get_image(&qimg_out)?;
-    let rimage = utils::get_image(&rimg_out)?;
+        let (image, _) = utils::get_image(&img_out)?;
+        images.push((image, name));
+    }
+
+    Ok(images)
+}
 
-    if utils::is_same_width_height(&qimage, &rimage) {
-        let (dssim, _) = attr.compare(&qimage.0, &rimage.0);
-        result.add(qimage.1, rimage.1, f64::from(dssim));
-    } else {
-        utils::eimgprint(&qimage, &rimage);
-        process::exit(1);
+/// Compares two genomes based on their previously drawn CGR images
+pub fn compare_images(
+    query: &(DssimImage<f32>, String),
+    reference: &(DssimImage<f32>, String),
+) -> anyhow::Result<SSIMResult> {
+    if !utils::is_same_width_height(query, reference) {
+        utils::eimgprint(query, reference);
+        anyhow::bail!("Cannot compare CGR images of different sizes");
     }
 
-    Ok(result)
+    let attr = dssim_core::Dssim::new();
+    let (dssim, _) = attr.compare(&query.0, &reference.0);
+
+    Ok(SSIMResult {
+        query: query.1.clone(),
+        reference: reference.1.clone(),
+        ssim: f64::from(dssim),
+        fcgr_k: None,
+    })
 }
 
 #[cfg(test)]
@@ -178,13 +1026,11 @@ mod tests {
     use std::fs;
 
     use super::*;
+    use crate::utils::test_utils::record;
 
     #[test]
     fn test_dna_to_chaos() {
-        let seq = fasta::Record::new(
-            fasta::record::Definition::new("sq0", None),
-            fasta::record::Sequence::from(b"TAGCA".to_vec()),
-        );
+        let seq = record("sq0", b"TAGCA");
 
         assert_eq!(
             Chaos {
@@ -195,9 +1041,342 @@ mod tests {
                     (0.6250_f64, -0.1250_f64),
                     (-0.1875_f64, -0.5625_f64),
                     (0.40625_f64, 0.21875_f64)
-                ]
+                ],
+                positions: vec![0, 1, 2, 3, 4],
+                segments: vec![Segment {
+                    id: "sq0".to_string(),
+                    start: 0,
+                    length: 5
+                }],
+                alphabet: Alphabet::nucleotides(),
             },
-            seq.record_to_chaos()
+            seq.record_to_chaos(&Alphabet::nucleotides())
+        );
+    }
+
+    #[test]
+    fn test_kmer_cell_holds_points_ending_with_kmer() {
+        let seq = b"GATTACACGGTCAGT";
+        let chaos = record("sq0", seq).record_to_chaos(&Alphabet::nucleotides());
+
+        let dna = Alphabet::nucleotides();
+        for k in 1..=4 {
+            for end in k..=seq.len() {
+                let ((x, y), half) = kmer_cell(&seq[end - k..end], &dna).unwrap();
+                let (px, py) = chaos.cgrs[end - 1];
+                assert!((px - x).abs() <= half && (py - y).abs() <= half);
+            }
+        }
+        assert_eq!(kmer_cell(b"A", &dna), Some(((0.5, 0.5), 0.5)));
+        assert_eq!(kmer_cell(b"AN", &dna), None);
+    }
+
+    #[test]
+    fn test_kmer_overlay() {
+        let options = DrawOptions {
+            kmer_grid: Some(2),
+            motif: vec![String::from("CG")],
+            ..Default::default()
+        };
+        let overlay = kmer_overlay(&options, &Alphabet::nucleotides());
+        assert_eq!(overlay.lines, vec![-0.5, 0.0, 0.5]);
+        assert_eq!(overlay.labels.len(), 16);
+        assert!(overlay.labels.contains(&(String::from("AA"), (0.75, 0.75))));
+        // The last nucleotide picks the quadrant: C sub-quadrant of the G quadrant
+        assert_eq!(overlay.motifs, vec![((0.25, -0.75), 0.25)]);
+
+        // Swapping C and G swaps their corners
+        let swapped = alphabet::parse_alphabet("A,T,G,C").unwrap();
+        let overlay = kmer_overlay(&options, &swapped);
+        assert_eq!(overlay.motifs, vec![((-0.25, -0.75), 0.25)]);
+        assert!(overlay
+            .labels
+            .contains(&(String::from("GG"), (-0.75, -0.75))));
+
+        assert_eq!(parse_motif("cg"), Ok(String::from("CG")));
+        assert!(parse_motif("C-G").is_err());
+        assert!(parse_motif("").is_err());
+    }
+
+    #[test]
+    fn test_read_chaos_record_modes() {
+        let fasta = ">c1\nA\n>c2\nTC\n";
+
+        let records = read_chaos(
+            fasta.as_bytes(),
+            RecordMode::PerRecord,
+            &AlphabetOptions::default(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].id, "c2");
+
+        // The second record restarts from the centre instead of carrying on from c1
+        let assembly = read_chaos(
+            fasta.as_bytes(),
+            RecordMode::Assembly,
+            &AlphabetOptions::default(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            assembly,
+            vec![Chaos {
+                id: "c1".to_string(),
+                cgrs: vec![(0.5, 0.5), (-0.5, 0.5), (-0.75, -0.25)],
+                positions: vec![0, 0, 1],
+                segments: vec![
+                    Segment {
+                        id: "c1".to_string(),
+                        start: 0,
+                        length: 1
+                    },
+                    Segment {
+                        id: "c2".to_string(),
+                        start: 1,
+                        length: 2
+                    }
+                ],
+                alphabet: Alphabet::nucleic_acids(),
+            }]
+        );
+
+        // RNA plays the same game as DNA
+        let rna = read_chaos(
+            fasta.replace('T', "U").as_bytes(),
+            RecordMode::Assembly,
+            &AlphabetOptions::default(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(rna, assembly);
+    }
+
+    #[test]
+    fn test_canonical_chaos() {
+        let fasta = ">c1\nAACGTTTGCAGG\n";
+        let reverse = ">c1\nCCTGCAAACGTT\n";
+        let read = |fasta: &str| {
+            read_chaos(
+                fasta.as_bytes(),
+                RecordMode::Assembly,
+                &AlphabetOptions::default(),
+                true,
+            )
+            .unwrap()
+            .remove(0)
+        };
+        let (chaos, reverse) = (read(fasta), read(reverse));
+        assert_eq!(chaos.cgrs.len(), 24);
+        // The reverse strand points come after the forward ones, at forward positions
+        assert_eq!(chaos.positions[12..15], [11, 10, 9]);
+        assert_eq!(chaos.segments[0].length, 12);
+
+        // Both strands give the same points
+        let sorted = |chaos: &Chaos| {
+            let mut points = chaos.cgrs.clone();
+            points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            points
+        };
+        assert_eq!(sorted(&chaos), sorted(&reverse));
+
+        let protein = AlphabetOptions {
+            alphabet: CgrAlphabet::Protein,
+            vertices: None,
+        };
+        assert!(read_chaos(fasta.as_bytes(), RecordMode::Assembly, &protein, true).is_err());
+    }
+
+    #[test]
+    fn test_protein_chaos() {
+        let fasta = ">p1\nMKVLAAGIVGLLLAQ\n";
+        let chaos = &read_chaos(
+            fasta.as_bytes(),
+            RecordMode::Assembly,
+            &AlphabetOptions::default(),
+            false,
+        )
+        .unwrap()[0];
+        assert_eq!(chaos.alphabet, Alphabet::protein(ProteinAlphabet::Full));
+        assert_eq!(chaos.cgrs.len(), 15);
+        // M moves most of the way from the centre to its vertex
+        let (mx, my) = chaos.alphabet.vertex(b'M').unwrap();
+        let ratio = alphabet::contraction(20);
+        assert!((chaos.cgrs[0].0 - ratio * mx).abs() < 1e-12);
+        assert!((chaos.cgrs[0].1 - ratio * my).abs() < 1e-12);
+
+        // Reduced alphabets put grouped amino acids on the same vertex
+        let hp = Alphabet::protein(ProteinAlphabet::Hp);
+        assert_eq!(hp.vertex(b'D'), hp.vertex(b'E'));
+        assert_ne!(hp.vertex(b'D'), hp.vertex(b'K'));
+        assert_eq!(hp.vertex(b'X'), None);
+
+        let dna = &read_chaos(
+            ">s\nACGT\n".as_bytes(),
+            RecordMode::Assembly,
+            &AlphabetOptions::default(),
+            false,
+        )
+        .unwrap()[0];
+        assert_eq!(dna.alphabet, Alphabet::nucleic_acids());
+        let options = AlphabetOptions {
+            alphabet: CgrAlphabet::Dna,
+            vertices: None,
+        };
+        let forced =
+            &read_chaos(fasta.as_bytes(), RecordMode::Assembly, &options, false).unwrap()[0];
+        // Only the A and G residues of the protein are nucleotides
+        assert_eq!(forced.cgrs.len(), 5);
+    }
+
+    #[test]
+    fn test_custom_vertices_play_uracil() {
+        let chaos = |groups: &str| {
+            let options = AlphabetOptions {
+                alphabet: CgrAlphabet::Auto,
+                vertices: Some(groups.parse().unwrap()),
+            };
+            read_chaos(
+                ">r\nACGU\n".as_bytes(),
+                RecordMode::Assembly,
+                &options,
+                false,
+            )
+            .unwrap()
+            .remove(0)
+        };
+        let swapped = chaos("T,A,G,C");
+        assert_eq!(swapped.cgrs.len(), 4);
+        assert_eq!(swapped.alphabet.vertex(b'U'), swapped.alphabet.vertex(b'T'));
+        // Without a T vertex, U stays unknown
+        assert_eq!(chaos("A,C,G,N").cgrs.len(), 3);
+    }
+
+    #[test]
+    fn test_colouring_gradient() {
+        let chaos = &read_chaos(
+            ">s\nACNGT\n".as_bytes(),
+            RecordMode::Assembly,
+            &AlphabetOptions::default(),
+            false,
+        )
+        .unwrap()[0];
+        let options = DrawOptions {
+            gradient: true,
+            ..Default::default()
+        };
+        let colouring = chaos.colouring(&options, None);
+        assert_eq!(colouring.points.len(), 4);
+        assert_eq!(colouring.points[0].1, options.colour_map.colour(0.0));
+        // G is at position 3 of 5, the N being skipped
+        assert_eq!(colouring.points[2].1, options.colour_map.colour(0.75));
+        assert_eq!(colouring.points[3].1, options.colour_map.colour(1.0));
+        assert!(colouring.legend.is_empty());
+    }
+
+    #[test]
+    fn test_colouring_features_on_both_strands() {
+        let fasta = ">c1\nACGTTGCA\n";
+        let chaos = &read_chaos(
+            fasta.as_bytes(),
+            RecordMode::Assembly,
+            &AlphabetOptions::default(),
+            true,
+        )
+        .unwrap()[0];
+        let features = [Feature {
+            seqid: "c1".to_string(),
+            start: 2,
+            end: 5,
+            kind: "CDS".to_string(),
+        }];
+        let colouring = chaos.colouring(&DrawOptions::default(), Some(&features));
+
+        // Positions 2 to 4 are annotated on both strands, and drawn last
+        let cds = render::category_colour(0);
+        let annotated: Vec<(f64, f64)> = colouring
+            .points
+            .iter()
+            .filter(|p| p.1 == cds)
+            .map(|p| p.0)
+            .collect();
+        let expected: Vec<(f64, f64)> = chaos
+            .cgrs
+            .iter()
+            .zip(&chaos.positions)
+            .filter(|&(_, &p)| (2..5).contains(&p))
+            .map(|(&point, _)| point)
+            .collect();
+        assert_eq!(chaos.cgrs.len(), 16);
+        assert_eq!(annotated, expected);
+        assert_eq!(annotated.len(), 6);
+    }
+
+    #[test]
+    fn test_colouring_features_and_highlight() {
+        let fasta = ">c1\nAAAA\n>c2\nCCCC\n";
+        let chaos = &read_chaos(
+            fasta.as_bytes(),
+            RecordMode::Assembly,
+            &AlphabetOptions::default(),
+            false,
+        )
+        .unwrap()[0];
+        let feature = |start, end, kind: &str| Feature {
+            seqid: "c2".to_string(),
+            start,
+            end,
+            kind: kind.to_string(),
+        };
+        let features = [feature(0, 4, "gene"), feature(1, 3, "CDS")];
+        let options = DrawOptions::default();
+
+        let colouring = chaos.colouring(&options, Some(&features));
+        let colours: Vec<RGBColor> = colouring.points.iter().map(|p| p.1).collect();
+        let (gene, cds) = (render::category_colour(0), render::category_colour(1));
+        // Unannotated c1 points are drawn first, nested CDS take precedence over the gene
+        assert_eq!(
+            colours,
+            vec![BLACK, BLACK, BLACK, BLACK, gene, cds, cds, gene]
+        );
+        assert_eq!(
+            colouring.legend,
+            vec![
+                ("gene".to_string(), gene),
+                ("CDS".to_string(), cds),
+                ("unannotated".to_string(), BLACK)
+            ]
+        );
+
+        let options = DrawOptions {
+            highlight: Some(annotation::parse_region("c1:2-3").unwrap()),
+            ..Default::default()
+        };
+        let colouring = chaos.colouring(&options, None);
+        let highlighted: Vec<(f64, f64)> = colouring
+            .points
+            .iter()
+            .filter(|p| p.1 == RED)
+            .map(|p| p.0)
+            .collect();
+        assert_eq!(highlighted, chaos.cgrs[1..3].to_vec());
+        assert_eq!(colouring.points.last().unwrap().1, RED);
+    }
+
+    #[test]
+    fn test_record_output() {
+        assert_eq!(
+            record_output(Path::new("out/cgr.png"), "contig_1"),
+            PathBuf::from("out/cgr_contig_1.png")
+        );
+        assert_eq!(
+            record_output(Path::new("cgr.svg"), "chr2"),
+            PathBuf::from("cgr_chr2.svg")
+        );
+        assert_eq!(
+            record_output(Path::new("out/cgr.png"), "../gi|123|ref:NC_1.2\\x*"),
+            PathBuf::from("out/cgr_.._gi_123_ref_NC_1.2_x_.png")
         );
     }
 
@@ -213,13 +1392,155 @@ mod tests {
                 (-0.1875_f64, -0.5625_f64),
                 (0.40625_f64, 0.21875_f64),
             ],
+            positions: vec![0, 1, 2, 3, 4],
+            segments: vec![Segment {
+                id: "sq0".to_string(),
+                start: 0,
+                length: 5,
+            }],
+            alphabet: Alphabet::nucleotides(),
         };
 
         let ot = PathBuf::from(odir);
         std::fs::create_dir(&ot).unwrap();
 
-        chaos.draw(Some(ot.clone())).unwrap();
+        let image = ot.join("sq0.png");
+        chaos
+            .draw(Some(image.clone()), &DrawOptions::default(), None)
+            .unwrap();
+        assert!(image.exists());
 
         fs::remove_dir_all(ot).unwrap();
     }
+
+    #[test]
+    fn test_draw_options() {
+        let dir = tempdir().unwrap();
+        let chaos = Chaos {
+            id: "sq0".to_string(),
+            cgrs: vec![(0.5, 0.5), (-0.25, 0.75)],
+            positions: vec![0, 1],
+            segments: vec![Segment {
+                id: "sq0".to_string(),
+                start: 0,
+                length: 2,
+            }],
+            alphabet: Alphabet::nucleotides(),
+        };
+        let options = DrawOptions {
+            size: 64,
+            height: Some(32),
+            point_size: 0,
+            colour: RED,
+            opacity: 0.5,
+            no_axes: true,
+            no_mesh: true,
+            dpi: 300,
+            ..Default::default()
+        };
+
+        let image = dir.path().join("sq0.png");
+        chaos.draw(Some(image.clone()), &options, None).unwrap();
+        let (_, name) = utils::get_image(&image).unwrap();
+        assert!(name.ends_with("sq0.png"));
+
+        let decoded = image::open(&image).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 32));
+    }
+
+    #[test]
+    fn test_draw_density() {
+        let dir = tempdir().unwrap();
+        let chaos = record("sq0", &b"ACGTTGCAACGGTCAGTCCA".repeat(20))
+            .record_to_chaos(&Alphabet::nucleotides());
+        let options = DrawOptions {
+            size: 256,
+            mode: DrawMode::Density,
+            scale: Scale::Linear,
+            colour_map: ColourMap::Magma,
+            ..Default::default()
+        };
+
+        let image = dir.path().join("sq0.png");
+        chaos.draw(Some(image.clone()), &options, None).unwrap();
+
+        let decoded = image::open(&image).unwrap().to_rgb8();
+        assert_eq!(decoded.dimensions(), (256, 256));
+        // The top of the colour bar, just below its border, has the colour of the largest count
+        let top = decoded.get_pixel(256 - LEGEND_WIDTH + 20, 11).0;
+        let RGBColor(r, g, b) = ColourMap::Magma.colour(1.0);
+        for (actual, expected) in top.into_iter().zip([r, g, b]) {
+            assert!(actual.abs_diff(expected) <= 3);
+        }
+    }
+
+    #[test]
+    fn test_draw_raster_is_reproducible() {
+        let dir = tempdir().unwrap();
+        let chaos =
+            record("sq0", &b"ACGTTGCAACGG".repeat(10)).record_to_chaos(&Alphabet::nucleotides());
+        let options = DrawOptions {
+            size: 128,
+            corner_labels: true,
+            raster: true,
+            ..Default::default()
+        };
+
+        let (first, second) = (dir.path().join("a.png"), dir.path().join("b.png"));
+        chaos.draw(Some(first.clone()), &options, None).unwrap();
+        chaos.draw(Some(second.clone()), &options, None).unwrap();
+        assert_eq!(
+            std::fs::read(&first).unwrap(),
+            std::fs::read(&second).unwrap()
+        );
+        assert_eq!(
+            image::open(&first).unwrap().to_rgb8().dimensions(),
+            (128, 128)
+        );
+    }
+
+    #[test]
+    fn test_draw_vector_formats() {
+        let dir = tempdir().unwrap();
+        let chaos = record("sq0", b"ACGTTGCAACGG").record_to_chaos(&Alphabet::nucleotides());
+
+        for mode in [DrawMode::Points, DrawMode::Density] {
+            let options = DrawOptions {
+                size: 128,
+                mode,
+                ..Default::default()
+            };
+
+            let svg = dir.path().join("sq0.svg");
+            chaos.draw(Some(svg.clone()), &options, None).unwrap();
+            let content = std::fs::read_to_string(&svg).unwrap();
+            assert!(content.starts_with("<svg"));
+            let shape = if mode == DrawMode::Points {
+                "<circle"
+            } else {
+                "<rect"
+            };
+            assert!(content.contains(shape));
+
+            let pdf = dir.path().join("sq0.pdf");
+            chaos.draw(Some(pdf.clone()), &options, None).unwrap();
+            assert!(std::fs::read(&pdf).unwrap().starts_with(b"%PDF-"));
+        }
+    }
+
+    #[test]
+    fn test_parse_colour() {
+        assert_eq!(parse_colour("Black"), Ok(BLACK));
+        assert_eq!(parse_colour("#ff8000"), Ok(RGBColor(255, 128, 0)));
+        assert_eq!(parse_colour("1f2e3d"), Ok(RGBColor(31, 46, 61)));
+        assert!(parse_colour("#12345").is_err());
+        assert!(parse_colour("purple-ish").is_err());
+    }
+
+    #[test]
+    fn test_parse_opacity() {
+        assert_eq!(parse_opacity("0.25"), Ok(0.25));
+        assert!(parse_opacity("1.5").is_err());
+        assert!(parse_opacity("half").is_err());
+    }
 }
@@ -0,0 +1,334 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use rayon::prelude::*;
+
+use crate::cgr::RecordMode;
+use crate::db::Database;
+use crate::fcgr::Fcgr;
+
+/// Reads a tab-separated mapping of reference files to taxa.
+///
+/// Lines starting with `#` are ignored.
+pub fn read_labels<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<(String, String)>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .comment(Some(b'#'))
+        .from_path(path.as_ref())
+        .with_context(|| format!("Failed to open {}", path.as_ref().display()))?;
+
+    let mut labels = Vec::new();
+    for (i, result) in rdr.deserialize::<(String, String)>().enumerate() {
+        let (file, taxon) =
+            result.with_context(|| format!("Error parsing label at line {}", i + 1))?;
+        labels.push((file, taxon));
+    }
+    Ok(labels)
+}
+
+/// k-nearest-neighbour classifier over labelled FCGR signatures
+pub struct Classifier {
+    /// Reference signatures
+    db: Database,
+
+    /// Taxon of each database entry
+    taxa: Vec<String>,
+
+    /// Number of neighbours voting
+    neighbours: usize,
+
+    /// Weight votes by inverse distance instead of simple majority
+    weighted: bool,
+}
+
+/// Taxon assigned to a sequence
+#[derive(Debug, PartialEq)]
+pub struct Prediction {
+    query: String,
+    taxon: String,
+    confidence: f64,
+}
+
+impl fmt::Display for Prediction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{:.4}",
+            Path::new(&self.query)
+                .file_name()
+                .unwrap()
+                .to_string_lossy(),
+            self.taxon,
+            self.confidence
+        )
+    }
+}
+
+impl Classifier {
+    /// Labels the entries of a database, matching them by source path or identifier
+    pub fn new(
+        db: Database,
+        labels: &[(String, String)],
+        neighbours: usize,
+        weighted: bool,
+    ) -> anyhow::Result<Self> {
+        let labels: HashMap<&str, &str> = labels
+            .iter()
+            .map(|(file, taxon)| (file.as_str(), taxon.as_str()))
+            .collect();
+
+        let taxa = db
+            .entries
+            .iter()
+            .map(|entry| {
+                labels
+                    .get(entry.source.as_str())
+                    .or_else(|| labels.get(entry.id.as_str()))
+                    .map(|taxon| taxon.to_string())
+                    .with_context(|| format!("No taxon found for reference {}", entry.source))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Classifier {
+            db,
+            taxa,
+            neighbours,
+            weighted,
+        })
+    }
+
+    /// Votes for a taxon among neighbours given as entry indices and distances.
+    ///
+    /// The confidence is the share of the votes won by the assigned taxon.
+    fn vote(&self, neighbours: &[(usize, f64)]) -> (String, f64) {
+        // Keep taxa in order of first appearance so that ties go to the closest one
+        let mut votes: Vec<(&str, f64)> = Vec::new();
+        for &(i, distance) in neighbours {
+            let weight = if self.weighted {
+                1.0 / (distance + f64::EPSILON)
+            } else {
+                1.0
+            };
+            match votes.iter_mut().find(|(taxon, _)| *taxon == self.taxa[i]) {
+                Some(vote) => vote.1 += weight,
+                None => votes.push((&self.taxa[i], weight)),
+            }
+        }
+
+        let total: f64 = votes.iter().map(|(_, w)| w).sum();
+        let mut best: Option<(&str, f64)> = None;
+        for (taxon, weight) in votes {
+            if best.is_none_or(|(_, w)| weight > w) {
+                best = Some((taxon, weight));
+            }
+        }
+
+        match best {
+            Some((taxon, weight)) => (taxon.to_string(), weight / total),
+            None => (String::from("unclassified"), 0.0),
+        }
+    }
+
+    /// Assigns a taxon to a signature
+    fn predict(&self, signature: &Fcgr) -> (String, f64) {
+        self.vote(&self.db.nearest(signature, self.neighbours))
+    }
+
+    /// Classifies query FASTA files, per record or as whole assemblies.
+    ///
+    /// In per-record mode, predictions are named after the file and the
+    /// record ID.
+    pub fn classify(
+        &self,
+        queries: &[String],
+        mode: RecordMode,
+    ) -> anyhow::Result<Vec<Prediction>> {
+        let signatures = queries
+            .par_iter()
+            .map(|query| self.db.query_signatures(query, mode))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(signatures
+            .into_par_iter()
+            .flatten()
+            .map(|(signature, query)| {
+                let (taxon, confidence) = self.predict(&signature);
+                Prediction {
+                    query,
+                    taxon,
+                    confidence,
+                }
+            })
+            .collect())
+    }
+
+    /// Leave-one-out cross-validation: classifies each reference against all the others
+    pub fn cross_validate(&self) -> CrossValidation {
+        let predicted: Vec<String> = (0..self.db.entries.len())
+            .into_par_iter()
+            .map(|i| {
+                let neighbours: Vec<(usize, f64)> = self
+                    .db
+                    .nearest(&self.db.entries[i].fcgr, self.neighbours + 1)
+                    .into_iter()
+                    .filter(|&(j, _)| j != i)
+                    .take(self.neighbours)
+                    .collect();
+                self.vote(&neighbours).0
+            })
+            .collect();
+
+        let mut per_taxon: Vec<(String, usize, usize)> = Vec::new();
+        for (truth, guess) in self.taxa.iter().zip(&predicted) {
+            let at = match per_taxon.iter().position(|(taxon, _, _)| taxon == truth) {
+                Some(at) => at,
+                None => {
+                    per_taxon.push((truth.clone(), 0, 0));
+                    per_taxon.len() - 1
+                }
+            };
+            per_taxon[at].1 += 1;
+            if truth == guess {
+                per_taxon[at].2 += 1;
+            }
+        }
+        per_taxon.sort();
+
+        CrossValidation { per_taxon }
+    }
+}
+
+/// Leave-one-out cross-validation report
+#[derive(Debug, PartialEq)]
+pub struct CrossValidation {
+    /// Taxon, number of references and number correctly classified
+    per_taxon: Vec<(String, usize, usize)>,
+}
+
+impl CrossValidation {
+    /// Writes the report as TSV, one line per taxon followed by the overall accuracy
+    pub fn write_all<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "#taxon\treferences\tcorrect\taccuracy")?;
+        let (mut total, mut correct) = (0, 0);
+        for (taxon, n, ok) in &self.per_taxon {
+            writeln!(writer, "{taxon}\t{n}\t{ok}\t{:.4}", *ok as f64 / *n as f64)?;
+            total += n;
+            correct += ok;
+        }
+        writeln!(
+            writer,
+            "all\t{total}\t{correct}\t{:.4}",
+            correct as f64 / total.max(1) as f64
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn classifier(neighbours: usize, weighted: bool) -> Classifier {
+        let dir = tempdir().unwrap();
+        let mut references = Vec::new();
+        let mut labels = Vec::new();
+        for (name, seq, taxon) in [
+            ("a1.fa", "AAAAAAAAAT", "adenine"),
+            ("a2.fa", "AAAAAAAATT", "adenine"),
+            ("a3.fa", "AAAAAAATTT", "adenine"),
+            ("c1.fa", "CCCCCCCCCG", "cytosine"),
+            ("c2.fa", "CCCCCCCCGG", "cytosine"),
+        ] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, format!(">{name}\n{seq}\n")).unwrap();
+            references.push(path.to_str().unwrap().to_string());
+            labels.push((name.to_string(), taxon.to_string()));
+        }
+        let db = Database::build(&references, 1, false).unwrap();
+        Classifier::new(db, &labels, neighbours, weighted).unwrap()
+    }
+
+    #[test]
+    fn test_read_labels() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("labels.tsv");
+        std::fs::write(&path, "# file\ttaxon\na.fa\tEscherichia\nb.fa\tBacillus\n").unwrap();
+        let labels = read_labels(&path).unwrap();
+        assert_eq!(
+            labels,
+            vec![
+                ("a.fa".to_string(), "Escherichia".to_string()),
+                ("b.fa".to_string(), "Bacillus".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_label() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("ref.fa");
+        std::fs::write(&path, ">r\nACGT\n").unwrap();
+        let db = Database::build(&[path.to_str().unwrap().to_string()], 1, false).unwrap();
+        assert!(Classifier::new(db, &[], 1, false).is_err());
+    }
+
+    #[test]
+    fn test_vote_majority_and_weighted() {
+        let majority = classifier(3, false);
+        // Two cytosine votes far away beat one close adenine vote
+        let neighbours = [(0, 0.1), (3, 0.5), (4, 0.6)];
+        assert_eq!(
+            majority.vote(&neighbours),
+            ("cytosine".to_string(), 2.0 / 3.0)
+        );
+
+        let weighted = classifier(3, true);
+        assert_eq!(weighted.vote(&neighbours).0, "adenine");
+    }
+
+    #[test]
+    fn test_classify_records() {
+        let classifier = classifier(1, false);
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("query.fa");
+        std::fs::write(&path, ">q1 contig\nAAAAAAAAAT\n>q2\nCCCCCCCCCG\n").unwrap();
+        let query = vec![path.to_str().unwrap().to_string()];
+
+        let predictions = classifier.classify(&query, RecordMode::PerRecord).unwrap();
+        let lines: Vec<String> = predictions.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "query.fa:q1\tadenine\t1.0000",
+                "query.fa:q2\tcytosine\t1.0000"
+            ]
+        );
+
+        // The whole assembly gets a single prediction
+        let predictions = classifier.classify(&query, RecordMode::Assembly).unwrap();
+        assert_eq!(predictions.len(), 1);
+        assert_eq!(predictions[0].query, query[0]);
+    }
+
+    #[test]
+    fn test_cross_validation() {
+        let report = classifier(1, false).cross_validate();
+        let mut output = Vec::new();
+        report.write_all(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "#taxon\treferences\tcorrect\taccuracy\n\
+             adenine\t3\t3\t1.0000\n\
+             cytosine\t2\t2\t1.0000\n\
+             all\t5\t5\t1.0000\n"
+        );
+    }
+}
@@ -3,9 +3,15 @@
 // This file may not be copied, modified, or distributed except according
 // to those terms.
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::{ffi::OsStr, path::PathBuf};
 
+use crate::alphabet::{self, Alphabet};
+use crate::cgr::{AlphabetOptions, DrawOptions, RecordMode};
+use crate::cluster::{ClusterMethod, Linkage};
+use crate::grep::Pattern;
+use crate::render::ColourMap;
+
 #[derive(Parser, Debug)]
 #[command(
     name = "chaoscoder",
@@ -34,11 +40,51 @@ pub enum Commands {
     /// Decode a sequence Integer Chaos Game Representation to DNA
     Decode(DecodeArgs),
 
+    /// Locate the substitutions between two Integer Chaos Game Representation files
+    Diff(DiffArgs),
+
     /// Draw Chaos Game Representation form sequence file
     Draw(DrawArgs),
 
+    /// Find the occurrences of a nucleotide motif in Integer Chaos Game Representation files
+    Grep(GrepArgs),
+
+    /// Count the k-mers of sequences, keyed by their Integer Chaos Game Representation
+    Kmers(KmersArgs),
+
     /// Structural Similarity Index Measure (SSIM) comparison of Chaos Game Representation images of genomes
     Compare(CompareArgs),
+
+    /// Draw the difference between the k-mer frequencies of two sequences
+    DiffImage(DiffImageArgs),
+
+    /// Draw an alignment-free dotplot of the window signatures of two genomes
+    Dotplot(DotplotArgs),
+
+    /// Scan genomes with sliding windows for regions of unusual k-mer composition
+    Scan(ScanArgs),
+
+    /// Screen the contigs of an assembly for contamination
+    Screen(ScreenArgs),
+
+    /// Search the nearest references of sequences in a reference database
+    Search(SearchArgs),
+
+    /// Assign taxa to sequences from their nearest labelled references
+    Classify(ClassifyArgs),
+
+    /// Cluster sequences by distance between their Frequency Chaos Game Representations
+    Cluster(ClusterArgs),
+
+    /// Manage reference databases of Frequency Chaos Game Representation signatures
+    #[command(subcommand)]
+    Db(DbCommands),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommands {
+    /// Build a reference database from FASTA files
+    Build(DbBuildArgs),
 }
 
 #[derive(Args, Debug)]
@@ -61,6 +107,15 @@ pub struct EncodeArgs {
     /// Strict mode that errors out if unknown characters are found
     #[arg(long = "strict", action = clap::ArgAction::SetTrue)]
     pub strict: bool,
+
+    /// Symbols of the corners as comma-separated groups, counterclockwise
+    /// from the top right corner, recorded in the output header
+    #[arg(long, default_value = "A,T,C,G", value_name = "GROUPS", value_parser = alphabet::parse_alphabet)]
+    pub vertices: Alphabet,
+
+    /// RNA mode: U takes the corner of T and is written back by decode
+    #[arg(long)]
+    pub rna: bool,
 }
 
 #[derive(Args, Debug)]
@@ -73,14 +128,45 @@ pub struct DecodeArgs {
     pub output: Option<PathBuf>,
 }
 
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// Reference BICGR file
+    pub reference: PathBuf,
+
+    /// Query BICGR file, records are matched to the reference by ID. Both files
+    /// should be encoded with --strict: unknown symbols inside blocks are not
+    /// detected from the integers and may give wrong substitutions, and a
+    /// warning is printed when a file shows unknown symbols
+    pub query: PathBuf,
+
+    /// Output VCF file, defaults to stdout
+    #[arg(short, value_parser = must_not_exist)]
+    pub output: Option<PathBuf>,
+}
+
 #[derive(Args, Debug)]
 pub struct DrawArgs {
     /// Input sequence file in FASTA format
     pub file: PathBuf,
 
-    /// Output file name (.png), defaults to sequence ID
+    /// Output file name (.png, .svg or .pdf), defaults to a PNG named after the
+    /// sequence ID
     #[arg(short, value_parser = validate_image_output)]
     pub output: Option<PathBuf>,
+
+    /// Draw multi-record files as one image per record or as a whole assembly
+    #[arg(long, value_enum, default_value_t = RecordMode::Assembly)]
+    pub records: RecordMode,
+
+    #[command(flatten)]
+    pub alphabet: AlphabetOptions,
+
+    /// Also play the chaos game on the reverse complement of each record
+    #[arg(long)]
+    pub canonical: bool,
+
+    #[command(flatten)]
+    pub options: DrawOptions,
 }
 
 #[derive(Args, Debug)]
@@ -99,6 +185,12 @@ pub struct CompareArgs {
     #[arg(long, conflicts_with = "reference")]
     pub refs: Option<PathBuf>,
 
+    /// Reference database built with `db build`. Scores are then the DSSIM of
+    /// the FCGR images of k-mers, not comparable with those of CGR images,
+    /// and are followed by a `fcgr-k<k>` column
+    #[arg(long, conflicts_with_all = ["reference", "refs", "allvsall", "alphabet", "vertices"])]
+    pub db: Option<PathBuf>,
+
     /// Output result to file
     #[arg(short)]
     pub output: Option<PathBuf>,
@@ -106,6 +198,347 @@ pub struct CompareArgs {
     /// Enable all-vs-all comparison
     #[arg(short = 'a', action = clap::ArgAction::SetTrue)]
     pub allvsall: bool,
+
+    /// Compare multi-record files record by record or as whole assemblies
+    #[arg(long, value_enum, default_value_t = RecordMode::Assembly)]
+    pub records: RecordMode,
+
+    #[command(flatten)]
+    pub alphabet: AlphabetOptions,
+
+    /// Combine each sequence with its reverse complement, so that contigs in
+    /// arbitrary orientation compare equal (requires a canonical --db)
+    #[arg(long)]
+    pub canonical: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SearchArgs {
+    /// Query sequence file
+    pub query: Option<PathBuf>,
+
+    /// File containing list of query sequences
+    #[arg(long, conflicts_with = "query")]
+    pub queries: Option<PathBuf>,
+
+    /// Reference database built with `db build`
+    #[arg(long)]
+    pub db: PathBuf,
+
+    /// Number of nearest references to report per query
+    #[arg(long = "top-k", default_value_t = 5, value_name = "INT")]
+    pub top_k: usize,
+
+    /// Output result to file
+    #[arg(short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ClassifyArgs {
+    /// Query sequence file
+    pub query: Option<PathBuf>,
+
+    /// File containing list of query sequences
+    #[arg(long, conflicts_with = "query")]
+    pub queries: Option<PathBuf>,
+
+    /// Tab-separated file mapping reference sequence files to taxa
+    #[arg(long)]
+    pub labels: PathBuf,
+
+    /// Reference database built with `db build`, instead of the labelled files
+    #[arg(long)]
+    pub db: Option<PathBuf>,
+
+    /// k-mer length of the FCGR signatures
+    #[arg(short, default_value_t = 7, value_name = "INT", value_parser = clap::value_parser!(u8).range(4..=10), conflicts_with = "db")]
+    pub k: u8,
+
+    /// Number of nearest references voting
+    #[arg(short, default_value_t = 5, value_name = "INT", value_parser = clap::value_parser!(u64).range(1..))]
+    pub neighbours: u64,
+
+    /// Weight votes by inverse distance instead of simple majority
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub weighted: bool,
+
+    /// Classify multi-record files record by record or as whole assemblies
+    #[arg(long, value_enum, default_value_t = RecordMode::Assembly)]
+    pub records: RecordMode,
+
+    /// Write a leave-one-out cross-validation report of the references to file
+    #[arg(long, value_name = "FILE")]
+    pub cv: Option<PathBuf>,
+
+    /// Output result to file
+    #[arg(short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ClusterArgs {
+    /// Input sequence files in FASTA format, every record is clustered
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Clustering algorithm
+    #[arg(short, long, value_enum, default_value_t = ClusterMethod::Hierarchical)]
+    pub method: ClusterMethod,
+
+    /// k-mer length of the FCGR signatures
+    #[arg(short, default_value_t = 7, value_name = "INT", value_parser = clap::value_parser!(u8).range(4..=10))]
+    pub k: u8,
+
+    /// Number of clusters (kmedoids)
+    #[arg(
+        short = 'c',
+        long,
+        value_name = "INT",
+        value_parser = clap::value_parser!(u64).range(1..),
+        required_if_eq("method", "kmedoids")
+    )]
+    pub clusters: Option<u64>,
+
+    /// Distance at which the tree is cut (hierarchical, the default method)
+    #[arg(long, value_name = "FLOAT", required_if_eq("method", "hierarchical"))]
+    pub threshold: Option<f64>,
+
+    /// Linkage criterion (hierarchical)
+    #[arg(long, value_enum, default_value_t = Linkage::Average)]
+    pub linkage: Linkage,
+
+    /// Neighbourhood radius (dbscan)
+    #[arg(long, value_name = "FLOAT", required_if_eq("method", "dbscan"))]
+    pub eps: Option<f64>,
+
+    /// Minimum number of sequences in a neighbourhood to form a cluster (dbscan)
+    #[arg(long = "min-points", default_value_t = 3, value_name = "INT", value_parser = clap::value_parser!(u64).range(1..))]
+    pub min_points: u64,
+
+    /// Write the medoid record of each cluster to a FASTA file
+    #[arg(long, value_name = "FILE", value_parser = must_not_exist)]
+    pub representatives: Option<PathBuf>,
+
+    /// Output result to file
+    #[arg(short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffImageArgs {
+    /// Query sequence file in FASTA format
+    pub query: PathBuf,
+
+    /// Reference sequence file in FASTA format
+    pub reference: PathBuf,
+
+    /// Output image file name (.png): red cells are k-mers over-represented in
+    /// the query, blue cells k-mers over-represented in the reference
+    #[arg(short, required = true, value_parser = validate_png_output)]
+    pub output: PathBuf,
+
+    /// k-mer length, the image has 2^k cells per side
+    #[arg(short, default_value_t = 7, value_name = "INT", value_parser = clap::value_parser!(u8).range(1..=10))]
+    pub k: u8,
+
+    /// Maximum image width in pixels, rounded down to a multiple of 2^k and at
+    /// least 2^k
+    #[arg(long, default_value_t = 1024, value_name = "INT")]
+    pub size: u32,
+
+    /// Colour map of the difference
+    #[arg(long = "colour-map", value_enum, default_value_t = ColourMap::Diverging)]
+    pub colour_map: ColourMap,
+
+    /// Save DSSIM's per-pixel similarity map of the two FCGRs (.png)
+    #[arg(long = "dssim-map", value_name = "FILE", value_parser = validate_png_output)]
+    pub dssim_map: Option<PathBuf>,
+
+    /// Number of most differing k-mers listed
+    #[arg(long, default_value_t = 20, value_name = "INT")]
+    pub top: usize,
+
+    /// Write the most differing k-mers to a TSV file instead of stdout
+    #[arg(long, value_name = "FILE", value_parser = must_not_exist)]
+    pub kmers: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct DotplotArgs {
+    /// Query sequence file in FASTA format, drawn from top to bottom
+    pub query: PathBuf,
+
+    /// Reference sequence file in FASTA format, drawn from left to right
+    pub reference: PathBuf,
+
+    /// Output image file name (.png)
+    #[arg(short, required = true, value_parser = validate_png_output)]
+    pub output: PathBuf,
+
+    /// k-mer length of the FCGR signatures
+    #[arg(short, default_value_t = 4, value_name = "INT", value_parser = clap::value_parser!(u8).range(1..=10))]
+    pub k: u8,
+
+    /// Window length in bases
+    #[arg(long, default_value_t = 5000, value_name = "INT", value_parser = clap::value_parser!(u64).range(1..))]
+    pub window: u64,
+
+    /// Distance between the starts of consecutive windows in bases
+    #[arg(long, default_value_t = 5000, value_name = "INT", value_parser = clap::value_parser!(u64).range(1..))]
+    pub step: u64,
+
+    /// Maximum image width and height in pixels, windows being binned when there
+    /// are more windows than pixels
+    #[arg(long, default_value_t = 1024, value_name = "INT")]
+    pub size: u32,
+
+    /// Colour map of the similarity
+    #[arg(long = "colour-map", value_enum, default_value_t = ColourMap::Viridis)]
+    pub colour_map: ColourMap,
+
+    /// Write the similarity of every pair of windows to a TSV file
+    #[arg(long, value_name = "FILE", value_parser = must_not_exist)]
+    pub tsv: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct GrepArgs {
+    /// Nucleotide motif, IUPAC codes allowed
+    #[arg(value_parser = clap::value_parser!(Pattern))]
+    pub pattern: Pattern,
+
+    /// BICGR file to search
+    pub file: PathBuf,
+
+    /// Also search the reverse complement of the motif, reported on the - strand
+    #[arg(long)]
+    pub revcomp: bool,
+
+    /// Output BED file of the occurrences, defaults to stdout
+    #[arg(short, value_parser = must_not_exist)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct KmersArgs {
+    /// Input sequence file in FASTA format
+    pub file: PathBuf,
+
+    /// k-mer length
+    #[arg(short, default_value_t = 21, value_name = "INT", value_parser = clap::value_parser!(u8).range(1..=63))]
+    pub k: u8,
+
+    /// Count a k-mer and its reverse complement together
+    #[arg(long)]
+    pub canonical: bool,
+
+    /// Only output k-mers counted at least this many times
+    #[arg(long = "min-count", default_value_t = 1, value_name = "INT")]
+    pub min_count: u64,
+
+    /// Output format of the k-mer table
+    #[arg(long, value_enum, default_value_t = KmerFormat::Tsv)]
+    pub format: KmerFormat,
+
+    /// Output file, defaults to stdout
+    #[arg(short, value_parser = must_not_exist)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum KmerFormat {
+    /// k-mer, iCGR integers and count per line
+    Tsv,
+    /// Little-endian table of iCGR integers and counts
+    Binary,
+}
+
+#[derive(Args, Debug)]
+pub struct ScanArgs {
+    /// Input genome file in FASTA format, all records making the genome signature
+    pub file: PathBuf,
+
+    /// k-mer length of the FCGR signatures
+    #[arg(short, default_value_t = 4, value_name = "INT", value_parser = clap::value_parser!(u8).range(1..=10))]
+    pub k: u8,
+
+    /// Window length in bases
+    #[arg(long, default_value_t = 5000, value_name = "INT", value_parser = clap::value_parser!(u64).range(1..))]
+    pub window: u64,
+
+    /// Distance between the starts of consecutive windows in bases
+    #[arg(long, default_value_t = 1000, value_name = "INT", value_parser = clap::value_parser!(u64).range(1..))]
+    pub step: u64,
+
+    /// Robust z-score of the distance above which a window is an outlier
+    #[arg(long = "z-score", default_value_t = 3.5, value_name = "FLOAT")]
+    pub z_score: f64,
+
+    /// Write outlier windows with their z-score to a BED file
+    #[arg(long, value_name = "FILE")]
+    pub outliers: Option<PathBuf>,
+
+    /// Count k-mers on both strands
+    #[arg(long)]
+    pub canonical: bool,
+
+    /// Output bedGraph of window distances to file, each distance spanning the
+    /// step-long bin at the centre of its window
+    #[arg(short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ScreenArgs {
+    /// Assembly file in FASTA format
+    pub assembly: PathBuf,
+
+    /// k-mer length of the FCGR signatures, ignored with --db
+    #[arg(short, default_value_t = 4, value_name = "INT", value_parser = clap::value_parser!(u8).range(1..=10))]
+    pub k: u8,
+
+    /// Reference database built with `db build` to find the nearest reference of each contig
+    #[arg(long)]
+    pub db: Option<PathBuf>,
+
+    /// Robust z-score of the distance above which a contig is an outlier
+    #[arg(long = "z-score", default_value_t = 3.5, value_name = "FLOAT")]
+    pub z_score: f64,
+
+    /// Contigs shorter than this are reported but not screened
+    #[arg(long = "min-length", default_value_t = 1000, value_name = "INT")]
+    pub min_length: usize,
+
+    /// Report every contig, not only flagged ones
+    #[arg(long)]
+    pub all: bool,
+
+    /// Output result to file
+    #[arg(short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct DbBuildArgs {
+    /// Reference sequence files in FASTA format
+    pub files: Vec<PathBuf>,
+
+    /// File containing list of reference sequences
+    #[arg(long)]
+    pub refs: Option<PathBuf>,
+
+    /// k-mer length of the FCGR signatures
+    #[arg(short, default_value_t = 7, value_name = "INT", value_parser = clap::value_parser!(u8).range(4..=10))]
+    pub k: u8,
+
+    /// Count k-mers on both strands, for references and queries alike
+    #[arg(long)]
+    pub canonical: bool,
+
+    /// Output database file
+    #[arg(short, value_parser = must_not_exist)]
+    pub output: PathBuf,
 }
 
 fn must_not_exist(s: &str) -> Result<PathBuf, String> {
@@ -124,19 +557,30 @@ fn validate_image_output(s: &str) -> Result<PathBuf, String> {
         return Err(format!("{} should not already exist.", mp.display()));
     }
     if let Some(ext) = mp.extension() {
-        if ext != OsStr::new("png") {
+        if !["png", "svg", "pdf"].iter().any(|e| ext == OsStr::new(e)) {
             return Err(format!(
-                "{} should have png extension (.png).",
+                "{} should have png, svg or pdf extension (.png, .svg, .pdf).",
                 mp.display()
             ));
         }
     } else {
-        return Err("Output file must have png extension (.png)".to_string());
+        return Err("Output file must have png, svg or pdf extension".to_string());
     }
 
     Ok(mp)
 }
 
+fn validate_png_output(s: &str) -> Result<PathBuf, String> {
+    let path = must_not_exist(s)?;
+    if path.extension() != Some(OsStr::new("png")) {
+        return Err(format!(
+            "{} should have png extension (.png).",
+            path.display()
+        ));
+    }
+    Ok(path)
+}
+
 fn validate_block_width(val: &str) -> Result<usize, String> {
     match val.parse::<usize>() {
         Ok(v) => {
@@ -204,7 +648,9 @@ mod tests {
     fn test_validate_image_output_with_wrong_extension() {
         let result = validate_image_output("target/image.jpg");
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("should have png extension"));
+        assert!(result
+            .unwrap_err()
+            .contains("should have png, svg or pdf extension"));
     }
 
     #[test]
@@ -213,10 +659,34 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err(),
-            "Output file must have png extension (.png)"
+            "Output file must have png, svg or pdf extension"
         );
     }
 
+    #[test]
+    fn test_validate_png_output() {
+        assert_eq!(
+            validate_png_output("target/diff.png"),
+            Ok(std::path::PathBuf::from("target/diff.png"))
+        );
+        assert!(validate_png_output("target/diff.svg")
+            .unwrap_err()
+            .contains("should have png extension"));
+    }
+
+    #[test]
+    fn test_validate_image_output_with_vector_formats() {
+        for path in ["target/tmp_image.svg", "target/tmp_image.pdf"] {
+            if std::path::Path::new(path).exists() {
+                std::fs::remove_file(path).unwrap();
+            }
+            assert_eq!(
+                validate_image_output(path),
+                Ok(std::path::PathBuf::from(path))
+            );
+        }
+    }
+
     #[test]
     fn test_validate_image_output_with_existing_file() {
         let path = "target/existing.png";
@@ -0,0 +1,341 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+
+use crate::fcgr::Fcgr;
+
+/// Pairwise distances between signatures, stored as the condensed upper triangle
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceMatrix {
+    /// Number of signatures
+    n: usize,
+
+    /// Distances d(i, j) for i < j, row by row
+    values: Vec<f64>,
+}
+
+impl DistanceMatrix {
+    /// Computes the FCGR distances between all pairs of signatures in parallel
+    pub fn from_signatures(signatures: &[Fcgr]) -> Self {
+        let n = signatures.len();
+        let values = (0..n)
+            .into_par_iter()
+            .flat_map_iter(|i| (i + 1..n).map(move |j| signatures[i].distance(&signatures[j])))
+            .collect();
+        DistanceMatrix { n, values }
+    }
+
+    /// Number of signatures
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    /// Distance between signatures i and j
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        if i == j {
+            return 0.0;
+        }
+        let (i, j) = (i.min(j), i.max(j));
+        // Rows before i hold (n - 1) + (n - 2) + ... + (n - i) values
+        self.values[i * (2 * self.n - i - 1) / 2 + (j - i - 1)]
+    }
+
+    /// Sum of distances from a signature to a set of signatures
+    fn cost(&self, i: usize, members: &[usize]) -> f64 {
+        members.iter().map(|&j| self.get(i, j)).sum()
+    }
+}
+
+/// Clustering algorithm
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ClusterMethod {
+    /// Partition around a fixed number of medoids
+    Kmedoids,
+    /// Agglomerative clustering cut at a distance threshold
+    Hierarchical,
+    /// Density-based clustering, leaving isolated sequences as noise
+    Dbscan,
+}
+
+/// Linkage criterion of hierarchical clustering
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Linkage {
+    /// Distance between the closest members
+    Single,
+    /// Distance between the farthest members
+    Complete,
+    /// Mean distance between members (UPGMA)
+    Average,
+}
+
+/// Result of a clustering
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clustering {
+    /// Cluster of each signature, `None` for DBSCAN noise
+    pub(crate) assignments: Vec<Option<usize>>,
+
+    /// Medoid of each cluster: the member closest to all the others
+    pub(crate) medoids: Vec<usize>,
+}
+
+impl Clustering {
+    /// Numbers clusters by order of first appearance and finds their medoids
+    fn from_assignments(dist: &DistanceMatrix, raw: Vec<Option<usize>>) -> Self {
+        let mut renumber: Vec<(usize, usize)> = Vec::new();
+        let assignments: Vec<Option<usize>> = raw
+            .into_iter()
+            .map(|label| {
+                label.map(|l| match renumber.iter().find(|(old, _)| *old == l) {
+                    Some(&(_, new)) => new,
+                    None => {
+                        renumber.push((l, renumber.len()));
+                        renumber.len() - 1
+                    }
+                })
+            })
+            .collect();
+
+        let mut members = vec![Vec::new(); renumber.len()];
+        for (i, label) in assignments.iter().enumerate() {
+            if let Some(c) = label {
+                members[*c].push(i);
+            }
+        }
+        let medoids = members
+            .iter()
+            .map(|m| medoid(dist, m).expect("clusters are not empty"))
+            .collect();
+
+        Clustering {
+            assignments,
+            medoids,
+        }
+    }
+}
+
+/// Member of a set minimising the sum of distances to the others
+fn medoid(dist: &DistanceMatrix, members: &[usize]) -> Option<usize> {
+    members
+        .iter()
+        .copied()
+        .min_by(|&a, &b| dist.cost(a, members).total_cmp(&dist.cost(b, members)))
+}
+
+/// Partitions signatures around `k` medoids.
+///
+/// Medoids are initialised with the greedy BUILD step of PAM, then refined by
+/// alternately assigning signatures to their closest medoid and moving each
+/// medoid to the centre of its cluster, until assignments are stable.
+pub fn kmedoids(dist: &DistanceMatrix, k: usize) -> Clustering {
+    let n = dist.len();
+    let k = k.min(n);
+    let all: Vec<usize> = (0..n).collect();
+
+    // BUILD: start from the most central signature, then add the signature
+    // decreasing the total cost the most
+    let mut medoids: Vec<usize> = medoid(dist, &all).into_iter().collect();
+    let mut nearest: Vec<f64> = (0..n)
+        .map(|i| medoids.first().map_or(0.0, |&m| dist.get(i, m)))
+        .collect();
+    while medoids.len() < k {
+        let gain =
+            |c: usize| -> f64 { (0..n).map(|i| (nearest[i] - dist.get(i, c)).max(0.0)).sum() };
+        let candidate = (0..n)
+            .filter(|c| !medoids.contains(c))
+            .max_by(|&a, &b| gain(a).total_cmp(&gain(b)))
+            .expect("k is at most the number of signatures");
+        medoids.push(candidate);
+        for (i, d) in nearest.iter_mut().enumerate() {
+            *d = d.min(dist.get(i, candidate));
+        }
+    }
+
+    let closest = |medoids: &[usize], i: usize| -> usize {
+        (0..medoids.len())
+            .min_by(|&a, &b| dist.get(i, medoids[a]).total_cmp(&dist.get(i, medoids[b])))
+            .unwrap_or(0)
+    };
+
+    let mut assignments: Vec<usize> = (0..n).map(|i| closest(&medoids, i)).collect();
+    for _ in 0..100 {
+        for (c, m) in medoids.iter_mut().enumerate() {
+            let members: Vec<usize> = (0..n).filter(|&i| assignments[i] == c).collect();
+            if let Some(best) = medoid(dist, &members) {
+                *m = best;
+            }
+        }
+        let updated: Vec<usize> = (0..n).map(|i| closest(&medoids, i)).collect();
+        if updated == assignments {
+            break;
+        }
+        assignments = updated;
+    }
+
+    Clustering::from_assignments(dist, assignments.into_iter().map(Some).collect())
+}
+
+/// Agglomerative hierarchical clustering cut at a distance threshold.
+///
+/// Clusters are merged as long as their linkage distance does not exceed the
+/// threshold. Distances between clusters are updated with the Lance-Williams
+/// formula, which takes O(n^3) time overall.
+pub fn hierarchical(dist: &DistanceMatrix, threshold: f64, linkage: Linkage) -> Clustering {
+    let n = dist.len();
+    let mut d: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| dist.get(i, j)).collect())
+        .collect();
+    let mut size = vec![1usize; n];
+    let mut active = vec![true; n];
+    let mut labels: Vec<usize> = (0..n).collect();
+
+    loop {
+        let mut closest: Option<(usize, usize, f64)> = None;
+        for a in (0..n).filter(|&a| active[a]) {
+            for b in (a + 1..n).filter(|&b| active[b]) {
+                if closest.is_none_or(|(_, _, best)| d[a][b] < best) {
+                    closest = Some((a, b, d[a][b]));
+                }
+            }
+        }
+
+        let (a, b) = match closest {
+            Some((a, b, distance)) if distance <= threshold => (a, b),
+            _ => break,
+        };
+
+        // Merge b into a
+        for c in (0..n).filter(|&c| active[c] && c != a && c != b) {
+            let merged = match linkage {
+                Linkage::Single => d[a][c].min(d[b][c]),
+                Linkage::Complete => d[a][c].max(d[b][c]),
+                Linkage::Average => {
+                    (size[a] as f64 * d[a][c] + size[b] as f64 * d[b][c])
+                        / (size[a] + size[b]) as f64
+                }
+            };
+            d[a][c] = merged;
+            d[c][a] = merged;
+        }
+        size[a] += size[b];
+        active[b] = false;
+        for label in labels.iter_mut().filter(|l| **l == b) {
+            *label = a;
+        }
+    }
+
+    Clustering::from_assignments(dist, labels.into_iter().map(Some).collect())
+}
+
+/// Density-based clustering (DBSCAN).
+///
+/// Signatures with at least `min_points` signatures (themselves included)
+/// within `eps` are core points; clusters are grown from core points and
+/// signatures reachable from none of them are left as noise.
+pub fn dbscan(dist: &DistanceMatrix, eps: f64, min_points: usize) -> Clustering {
+    let n = dist.len();
+    let neighbours =
+        |i: usize| -> Vec<usize> { (0..n).filter(|&j| dist.get(i, j) <= eps).collect() };
+
+    let mut labels: Vec<Option<usize>> = vec![None; n];
+    let mut visited = vec![false; n];
+    let mut cluster = 0;
+
+    for i in 0..n {
+        if visited[i] {
+            continue;
+        }
+        visited[i] = true;
+        let seeds = neighbours(i);
+        if seeds.len() < min_points {
+            continue;
+        }
+
+        labels[i] = Some(cluster);
+        let mut queue = seeds;
+        while let Some(j) = queue.pop() {
+            if labels[j].is_none() {
+                labels[j] = Some(cluster);
+            }
+            if !visited[j] {
+                visited[j] = true;
+                let reachable = neighbours(j);
+                if reachable.len() >= min_points {
+                    queue.extend(reachable);
+                }
+            }
+        }
+        cluster += 1;
+    }
+
+    Clustering::from_assignments(dist, labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two well separated groups on a line and an outlier
+    fn points() -> DistanceMatrix {
+        let x: [f64; 7] = [0.0, 0.1, 0.2, 5.0, 5.1, 5.3, 20.0];
+        let n = x.len();
+        let mut values = Vec::new();
+        for i in 0..n {
+            for j in i + 1..n {
+                values.push((x[i] - x[j]).abs());
+            }
+        }
+        DistanceMatrix { n, values }
+    }
+
+    #[test]
+    fn test_distance_matrix_indexing() {
+        let dist = points();
+        assert_eq!(dist.get(0, 0), 0.0);
+        assert!((dist.get(1, 4) - 5.0).abs() < 1e-12);
+        assert_eq!(dist.get(4, 1), dist.get(1, 4));
+        assert!((dist.get(5, 6) - 14.7).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_distance_matrix_from_signatures() {
+        let a = Fcgr::from_sequence(b"AAAA", 1);
+        let b = Fcgr::from_sequence(b"AACC", 1);
+        let dist = DistanceMatrix::from_signatures(&[a.clone(), b.clone(), a]);
+        assert_eq!(dist.len(), 3);
+        assert_eq!(dist.get(0, 2), 0.0);
+        assert_eq!(dist.get(1, 2), dist.get(0, 1));
+    }
+
+    #[test]
+    fn test_kmedoids() {
+        let clustering = kmedoids(&points(), 3);
+        let expected: Vec<Option<usize>> = [0, 0, 0, 1, 1, 1, 2].into_iter().map(Some).collect();
+        assert_eq!(clustering.assignments, expected);
+        assert_eq!(clustering.medoids, vec![1, 4, 6]);
+    }
+
+    #[test]
+    fn test_hierarchical() {
+        let clustering = hierarchical(&points(), 1.0, Linkage::Average);
+        let expected: Vec<Option<usize>> = [0, 0, 0, 1, 1, 1, 2].into_iter().map(Some).collect();
+        assert_eq!(clustering.assignments, expected);
+
+        // Single linkage chains everything below the largest gap
+        let clustering = hierarchical(&points(), 5.0, Linkage::Single);
+        assert_eq!(clustering.medoids.len(), 2);
+    }
+
+    #[test]
+    fn test_dbscan_noise() {
+        let clustering = dbscan(&points(), 0.5, 2);
+        assert_eq!(
+            clustering.assignments,
+            vec![Some(0), Some(0), Some(0), Some(1), Some(1), Some(1), None]
+        );
+        assert_eq!(clustering.medoids, vec![1, 4]);
+    }
+}
@@ -0,0 +1,566 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use anyhow::Context;
+use itertools::Itertools;
+use noodles::fasta;
+use rayon::prelude::*;
+
+use crate::cgr::{self, RecordMode, SSIMResult};
+use crate::fcgr::Fcgr;
+use crate::index::VpTree;
+use crate::utils;
+
+/// Reference database of FCGR signatures
+///
+/// The database stores one signature per reference FASTA file so that repeated
+/// comparisons do not need to recompute them from the sequences. Entries are
+/// laid out as a vantage-point tree over FCGR distances, the radius of each
+/// node being stored with its vantage point, so that nearest-neighbour queries
+/// do not scan every reference.
+///
+/// Canonical databases combine each signature with the signature of the
+/// reverse complement, and query signatures are combined the same way.
+///
+/// ### BNF Grammar of a database file:
+/// ```text
+/// <db_file>     ::= <header_line> <entry_line>*
+/// <header_line> ::= "#chaoscoder-db" "\t" <k> ("\t" "canonical")? "\n"
+/// <entry_line>  ::= <id> "\t" <description> "\t" <source> "\t" <records> "\t" <length> "\t" <radius> "\t" <counts> "\n"
+///
+/// <k>           ::= "4" | "5" | "6" | "7" | "8" | "9" | "10"
+/// <id>          ::= [^\t\n]+
+/// <description> ::= [^\t\n]*
+/// <source>      ::= [^\t\n]+
+/// <records>     ::= [0-9]+
+/// <length>      ::= [0-9]+
+/// <radius>      ::= [0-9]+ ("." [0-9]+)?
+/// <counts>      ::= [0-9]+ ("," [0-9]+)*
+/// ```
+///
+/// Tabs and line breaks in IDs, descriptions and sources are written as spaces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Database {
+    /// k-mer length shared by all signatures
+    pub(crate) k: u8,
+
+    /// Whether signatures count k-mers on both strands
+    pub(crate) canonical: bool,
+
+    /// Reference signatures, in vantage-point tree order
+    pub(crate) entries: Vec<Entry>,
+
+    /// Nearest-neighbour index over the entries
+    pub(crate) index: VpTree,
+}
+
+/// A single reference signature with its metadata
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Reference identifier: the FASTA file name
+    pub(crate) id: String,
+
+    /// Definition line of the first record of the reference
+    pub(crate) desc: String,
+
+    /// Path of the FASTA file the signature was computed from
+    pub(crate) source: String,
+
+    /// Number of FASTA records in the reference
+    pub(crate) records: usize,
+
+    /// Total number of nucleotides in the reference
+    pub(crate) length: usize,
+
+    /// FCGR signature of the reference
+    pub(crate) fcgr: Fcgr,
+}
+
+/// A reference found close to a query
+#[derive(Debug)]
+pub struct Hit {
+    query: String,
+    reference: String,
+    distance: f64,
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}",
+            Path::new(&self.query)
+                .file_name()
+                .unwrap()
+                .to_string_lossy(),
+            self.reference,
+            self.distance
+        )
+    }
+}
+
+const HEADER: &str = "#chaoscoder-db";
+const CANONICAL: &str = "canonical";
+
+/// k-mer lengths of database signatures
+pub const K_RANGE: RangeInclusive<u8> = 4..=10;
+
+/// Checks that k is a k-mer length accepted by `db build`
+fn check_k(k: u8) -> anyhow::Result<()> {
+    if !K_RANGE.contains(&k) {
+        anyhow::bail!(
+            "Database k-mer length must be in {}..={}, got {k}",
+            K_RANGE.start(),
+            K_RANGE.end()
+        );
+    }
+    Ok(())
+}
+
+/// Field of an entry line, with tabs and line breaks replaced by spaces
+fn field(value: &str) -> String {
+    value.replace(['\t', '\r', '\n'], " ")
+}
+
+impl Entry {
+    /// Computes the signature of all the records of a FASTA file
+    pub fn from_fasta(path: &str, k: u8) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open {path}"))?;
+        let mut reader = fasta::Reader::new(BufReader::new(file));
+
+        let mut fcgr = Fcgr::new(k);
+        let mut desc = String::new();
+        let mut records = 0;
+        let mut length = 0;
+
+        for result in reader.records() {
+            let record = result?;
+            if records == 0 {
+                desc = match record.description() {
+                    Some(d) => format!("{} {}", record.name(), d),
+                    None => record.name().to_string(),
+                };
+            }
+            fcgr.add_sequence(record.sequence().as_ref());
+            records += 1;
+            length += record.sequence().len();
+        }
+
+        if records == 0 {
+            anyhow::bail!("No sequence found in {path}");
+        }
+
+        let id = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string());
+
+        Ok(Entry {
+            id,
+            desc,
+            source: path.to_string(),
+            records,
+            length,
+            fcgr,
+        })
+    }
+}
+
+impl Database {
+    /// Builds a database from reference FASTA files, computing signatures in parallel
+    pub fn build(references: &[String], k: u8, canonical: bool) -> anyhow::Result<Self> {
+        let entries = references
+            .par_iter()
+            .map(|reference| {
+                let mut entry = Entry::from_fasta(reference, k)?;
+                if canonical {
+                    entry.fcgr = entry.fcgr.canonical();
+                }
+                Ok(entry)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let (entries, index) = VpTree::build(entries, |a, b| a.fcgr.distance(&b.fcgr));
+
+        Ok(Database {
+            k,
+            canonical,
+            entries,
+            index,
+        })
+    }
+
+    /// Signature of a query comparable with the references
+    fn query_signature(&self, fcgr: Fcgr) -> Fcgr {
+        if self.canonical {
+            fcgr.canonical()
+        } else {
+            fcgr
+        }
+    }
+
+    /// Computes the signatures of a query FASTA file, per record or for the whole assembly
+    pub(crate) fn query_signatures(
+        &self,
+        query: &str,
+        mode: RecordMode,
+    ) -> anyhow::Result<Vec<(Fcgr, String)>> {
+        if mode == RecordMode::Assembly {
+            return Ok(vec![(
+                Entry::from_fasta(query, self.k)?.fcgr,
+                query.to_string(),
+            )]);
+        }
+
+        let records = utils::read_records(query)?;
+        if records.is_empty() {
+            anyhow::bail!("No sequence found in {query}");
+        }
+        let several = records.len() > 1;
+        Ok(records
+            .iter()
+            .map(|record| {
+                let name = if several {
+                    format!("{query}:{}", record.name())
+                } else {
+                    query.to_string()
+                };
+                (
+                    Fcgr::from_sequence(record.sequence().as_ref(), self.k),
+                    name,
+                )
+            })
+            .collect())
+    }
+
+    /// Compares query FASTA files against every reference of the database.
+    ///
+    /// References are only stored as FCGRs, so the DSSIM is computed on the
+    /// 2^k x 2^k FCGR images of queries and references rather than on drawn
+    /// CGR images, and results are labelled as such.
+    pub fn compare(&self, queries: &[String], mode: RecordMode) -> anyhow::Result<Vec<SSIMResult>> {
+        let qimages = queries
+            .par_iter()
+            .map(|query| self.query_signatures(query, mode))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .map(|(fcgr, name)| (self.query_signature(fcgr).to_image(), name))
+            .collect::<Vec<_>>();
+        let rimages: Vec<_> = self
+            .entries
+            .par_iter()
+            .map(|entry| (entry.fcgr.to_image(), entry.id.clone()))
+            .collect();
+
+        let pairs: Vec<(usize, usize)> = (0..qimages.len())
+            .cartesian_product(0..rimages.len())
+            .collect();
+        pairs
+            .par_iter()
+            .map(|&(q, r)| {
+                cgr::compare_images(&qimages[q], &rimages[r]).map(|result| result.on_fcgr(self.k))
+            })
+            .collect()
+    }
+
+    /// Finds the `k` references closest to a signature, as entry indices and
+    /// distances. The signature is made canonical for canonical databases.
+    pub fn nearest(&self, signature: &Fcgr, k: usize) -> Vec<(usize, f64)> {
+        let signature = self.query_signature(signature.clone());
+        self.index
+            .search(k, |i| signature.distance(&self.entries[i].fcgr))
+    }
+
+    /// Finds the `top_k` references closest to each query FASTA file
+    pub fn search(&self, queries: &[String], top_k: usize) -> anyhow::Result<Vec<Hit>> {
+        let hits = queries
+            .par_iter()
+            .map(|query| {
+                let signature = Entry::from_fasta(query, self.k)?.fcgr;
+                Ok(self
+                    .nearest(&signature, top_k)
+                    .into_iter()
+                    .map(|(i, distance)| Hit {
+                        query: query.clone(),
+                        reference: self.entries[i].id.clone(),
+                        distance,
+                    })
+                    .collect::<Vec<_>>())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(hits.into_iter().flatten().collect())
+    }
+
+    /// Writes the database to a writer (e.g. file or stdout)
+    pub fn write_all<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "{HEADER}\t{}", self.k)?;
+        if self.canonical {
+            write!(writer, "\t{CANONICAL}")?;
+        }
+        writeln!(writer)?;
+        for (entry, radius) in self.entries.iter().zip(self.index.radii()) {
+            let counts = entry
+                .fcgr
+                .counts()
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                field(&entry.id),
+                field(&entry.desc),
+                field(&entry.source),
+                entry.records,
+                entry.length,
+                radius,
+                counts
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Reads a database from a buffered reader
+    ///
+    /// # Errors
+    /// Returns an error if the header is missing or if an entry is malformed.
+    pub fn read_from<R: BufRead>(reader: R) -> anyhow::Result<Self> {
+        let mut lines = reader.lines();
+
+        let header = lines.next().context("Empty database file")??;
+        let (k, canonical) = match header.split_once('\t') {
+            Some((HEADER, fields)) => {
+                let (k, canonical) = match fields.trim().split_once('\t') {
+                    Some((k, CANONICAL)) => (k, true),
+                    Some((_, flag)) => anyhow::bail!("Unknown database header field {flag}"),
+                    None => (fields, false),
+                };
+                let k = k
+                    .trim()
+                    .parse::<u8>()
+                    .context("Invalid k-mer length in database header")?;
+                check_k(k)?;
+                (k, canonical)
+            }
+            _ => anyhow::bail!("Missing {HEADER} header line"),
+        };
+
+        let mut entries = Vec::new();
+        let mut radii = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // Header is line 1
+            let lineno = i + 2;
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 7 {
+                anyhow::bail!(
+                    "Error parsing entry at line {lineno}: expected 7 fields, got {}",
+                    fields.len()
+                );
+            }
+            if fields[0].trim().is_empty() {
+                anyhow::bail!("Missing reference ID at line {lineno}");
+            }
+
+            radii.push(
+                fields[5]
+                    .parse::<f64>()
+                    .with_context(|| format!("Invalid radius at line {lineno}"))?,
+            );
+            let counts = fields[6]
+                .split(',')
+                .map(|c| c.parse::<u64>())
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Invalid k-mer count at line {lineno}"))?;
+
+            entries.push(Entry {
+                id: fields[0].to_string(),
+                desc: fields[1].to_string(),
+                source: fields[2].to_string(),
+                records: fields[3]
+                    .parse()
+                    .with_context(|| format!("Invalid record count at line {lineno}"))?,
+                length: fields[4]
+                    .parse()
+                    .with_context(|| format!("Invalid length at line {lineno}"))?,
+                fcgr: Fcgr::from_counts(k, counts)
+                    .with_context(|| format!("Invalid signature at line {lineno}"))?,
+            });
+        }
+
+        Ok(Database {
+            k,
+            canonical,
+            entries,
+            index: VpTree::from_radii(radii),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_entry_from_fasta() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("ref.fa");
+        std::fs::write(&path, ">c1 first contig\nACGTA\n>c2\nNNAC\n").unwrap();
+
+        let entry = Entry::from_fasta(path.to_str().unwrap(), 2).unwrap();
+        assert_eq!(entry.id, "ref.fa");
+        assert_eq!(entry.desc, "c1 first contig");
+        assert_eq!(entry.records, 2);
+        assert_eq!(entry.length, 9);
+        // 4 dimers in c1 and a single one in c2, none across records
+        assert_eq!(entry.fcgr.counts().iter().sum::<u64>(), 5);
+    }
+
+    #[test]
+    fn test_write_read_roundtrip() {
+        let mut counts = vec![0; 256];
+        counts[..4].copy_from_slice(&[1, 2, 0, 4]);
+        let db = Database {
+            k: 4,
+            canonical: false,
+            index: VpTree::from_radii(vec![0.0]),
+            entries: vec![Entry {
+                id: "ref.fa".to_string(),
+                desc: "c1 first contig".to_string(),
+                source: "data/ref.fa".to_string(),
+                records: 2,
+                length: 9,
+                fcgr: Fcgr::from_counts(4, counts).unwrap(),
+            }],
+        };
+
+        let mut output = Vec::new();
+        db.write_all(&mut output).unwrap();
+        assert!(String::from_utf8(output.clone()).unwrap().starts_with(
+            "#chaoscoder-db\t4\nref.fa\tc1 first contig\tdata/ref.fa\t2\t9\t0\t1,2,0,4,0,"
+        ));
+
+        let read = Database::read_from(Cursor::new(output)).unwrap();
+        assert_eq!(read, db);
+    }
+
+    #[test]
+    fn test_write_read_fields_with_tabs() {
+        let mut db = Database {
+            k: 4,
+            canonical: false,
+            index: VpTree::from_radii(vec![0.0]),
+            entries: vec![Entry {
+                id: "my\tref.fa".to_string(),
+                desc: "r1 desc\twith tab\nand line".to_string(),
+                source: "data/my\tref.fa".to_string(),
+                records: 1,
+                length: 4,
+                fcgr: Fcgr::from_sequence(b"ACGT", 4),
+            }],
+        };
+
+        let mut output = Vec::new();
+        db.write_all(&mut output).unwrap();
+        let read = Database::read_from(Cursor::new(output)).unwrap();
+        let entry = &mut db.entries[0];
+        entry.id = "my ref.fa".to_string();
+        entry.desc = "r1 desc with tab and line".to_string();
+        entry.source = "data/my ref.fa".to_string();
+        assert_eq!(read, db);
+    }
+
+    #[test]
+    fn test_read_invalid_k() {
+        for k in ["0", "3", "11", "64"] {
+            let input = format!("#chaoscoder-db\t{k}\n");
+            let error = Database::read_from(Cursor::new(input)).unwrap_err();
+            assert!(error.to_string().contains("k-mer length"), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_build_and_search() {
+        let dir = tempdir().unwrap();
+        let mut references = Vec::new();
+        for (name, seq) in [
+            ("a.fa", "AAAAAAAA"),
+            ("c.fa", "CCCCCCCC"),
+            ("ac.fa", "AAAACCCC"),
+        ] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, format!(">{name}\n{seq}\n")).unwrap();
+            references.push(path.to_str().unwrap().to_string());
+        }
+
+        let db = Database::build(&references, 4, false).unwrap();
+        let hits = db.search(&references[..1], 2).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].reference, "a.fa");
+        assert_eq!(hits[0].distance, 0.0);
+        assert_eq!(hits[1].reference, "ac.fa");
+
+        // Scores on FCGR images are labelled with their k-mer length
+        let results = db.compare(&references[..1], RecordMode::Assembly).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].to_string(), "a.fa\ta.fa\t0\tfcgr-k4");
+    }
+
+    #[test]
+    fn test_canonical_database() {
+        let dir = tempdir().unwrap();
+        let mut references = Vec::new();
+        for (name, seq) in [("fwd.fa", "AACGTTTGCAGG"), ("other.fa", "GGGGGGCCGGGG")] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, format!(">{name}\n{seq}\n")).unwrap();
+            references.push(path.to_str().unwrap().to_string());
+        }
+        let query = dir.path().join("rev.fa");
+        std::fs::write(&query, ">rev\nCCTGCAAACGTT\n").unwrap();
+        let query = vec![query.to_str().unwrap().to_string()];
+
+        // The reverse complement only matches its reference on both strands
+        let plain = Database::build(&references, 4, false).unwrap();
+        assert!(plain.search(&query, 1).unwrap()[0].distance > 0.0);
+        let canonical = Database::build(&references, 4, true).unwrap();
+        let hit = &canonical.search(&query, 1).unwrap()[0];
+        assert_eq!(hit.reference, "fwd.fa");
+        assert!(hit.distance < 1e-12);
+
+        let mut output = Vec::new();
+        canonical.write_all(&mut output).unwrap();
+        assert!(output.starts_with(b"#chaoscoder-db\t4\tcanonical\n"));
+        assert_eq!(Database::read_from(Cursor::new(output)).unwrap(), canonical);
+        assert!(Database::read_from(Cursor::new("#chaoscoder-db\t4\tother\n")).is_err());
+    }
+
+    #[test]
+    fn test_read_missing_header() {
+        let input = "ref.fa\t\tref.fa\t1\t4\t0\t1,0,0,0\n";
+        let result = Database::read_from(Cursor::new(input));
+        assert!(result.unwrap_err().to_string().contains("header"));
+    }
+
+    #[test]
+    fn test_read_wrong_signature_size() {
+        let input = "#chaoscoder-db\t4\nref.fa\t\tref.fa\t1\t4\t0\t1,0,0\n";
+        let result = Database::read_from(Cursor::new(input));
+        assert!(result.is_err());
+    }
+}
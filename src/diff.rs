@@ -0,0 +1,390 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+
+use rayon::prelude::*;
+
+use crate::bicgr::{Header, Record};
+
+/// A substitution of the query against the reference
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitution {
+    /// Record ID
+    pub seq_id: String,
+
+    /// 0-based position in the record
+    pub position: usize,
+
+    /// Symbol of the reference
+    pub reference: u8,
+
+    /// Symbol of the query
+    pub alternative: u8,
+}
+
+impl fmt::Display for Substitution {
+    /// Formats the substitution as a VCF data line, with a 1-based position
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t.\t{}\t{}\t.\t.\t.",
+            self.seq_id,
+            self.position + 1,
+            self.reference as char,
+            self.alternative as char
+        )
+    }
+}
+
+/// Whether two decoded symbols differ, T and U being the same nucleotide
+fn differ(a: u8, b: u8) -> bool {
+    let t = |s: u8| if s == b'U' { b'T' } else { s };
+    t(a) != t(b)
+}
+
+/// Locates the substitutions between two encodings of a sequence.
+///
+/// When both records were encoded with the same blocks and alphabet, the
+/// mismatches of each pair of blocks are read from the integers. Otherwise
+/// both records are decoded. Positions in the overlap of two blocks are
+/// reported once.
+///
+/// Reading mismatches from the integers expects records encoded with
+/// `--strict`. Without it, an unknown symbol at the start of a block leaves
+/// even integers and the block is decoded, but one further in the block goes
+/// unnoticed: it is read as the corner its missing bits point to, so that
+/// substitutions at or around unknown symbols may be misreported.
+///
+/// # Errors
+/// Returns an error if the sequences have different lengths.
+pub fn substitutions(
+    reference_header: &Header,
+    reference: &Record,
+    query_header: &Header,
+    query: &Record,
+) -> anyhow::Result<Vec<Substitution>> {
+    let (length, query_length) = (
+        reference.tri_integers.sequence_len(reference.overlap),
+        query.tri_integers.sequence_len(query.overlap),
+    );
+    if length != query_length {
+        anyhow::bail!(
+            "{} has {length} symbols in the reference and {query_length} in the query",
+            reference.seq_id
+        );
+    }
+    let substitution = |position, reference_symbol, alternative| Substitution {
+        seq_id: reference.seq_id.clone(),
+        position,
+        reference: reference_symbol,
+        alternative,
+    };
+
+    let same_blocks = reference.overlap == query.overlap
+        && reference_header.alphabet == query_header.alphabet
+        && reference.tri_integers.len() == query.tri_integers.len()
+        && reference
+            .tri_integers
+            .iter()
+            .zip(query.tri_integers.iter())
+            .all(|(a, b)| a.n == b.n);
+    if !same_blocks {
+        let (a, b) = (
+            reference_header.decode(reference)?,
+            query_header.decode(query)?,
+        );
+        return Ok(a
+            .bytes()
+            .zip(b.bytes())
+            .enumerate()
+            .filter(|&(_, (a, b))| differ(a, b))
+            .map(|(position, (a, b))| substitution(position, a, b))
+            .collect());
+    }
+
+    let overlap = reference.overlap as usize;
+    let mut found = Vec::new();
+    let mut start = 0;
+    for (i, (a, b)) in reference
+        .tri_integers
+        .iter()
+        .zip(query.tri_integers.iter())
+        .enumerate()
+    {
+        // The first symbols of a block were compared in the previous block
+        let shared = if i == 0 { 0 } else { overlap };
+        match a.mismatches(b) {
+            Ok(positions) => {
+                for position in positions.into_iter().filter(|&p| p >= shared) {
+                    found.push(substitution(
+                        start + position,
+                        reference_header.symbol_at(a.corner(position)?),
+                        query_header.symbol_at(b.corner(position)?),
+                    ));
+                }
+            }
+            // An unknown first symbol leaves even integers, so the block is decoded
+            Err(_) => {
+                let (x, y) = (
+                    reference_header.decode_block(a),
+                    query_header.decode_block(b),
+                );
+                for position in shared..a.n {
+                    if differ(x[position], y[position]) {
+                        found.push(substitution(start + position, x[position], y[position]));
+                    }
+                }
+            }
+        }
+        start += a.n.saturating_sub(overlap);
+    }
+    Ok(found)
+}
+
+/// ID of the first record with a block starting with an unknown symbol.
+///
+/// Such a block leaves even integers, which only happens in files encoded
+/// without `--strict`. Unknown symbols further in a block cannot be told apart
+/// from known ones, so a file without such blocks may still have some.
+pub fn unknown_symbols(records: &[Record]) -> Option<&str> {
+    records
+        .iter()
+        .find(|record| {
+            record
+                .tri_integers
+                .iter()
+                .any(|block| block.corner(0).is_err())
+        })
+        .map(|record| record.seq_id.as_str())
+}
+
+/// Substitutions between the records of two BICGR files, matched by ID
+#[derive(Debug)]
+pub struct Diff {
+    /// Compared records with their lengths, in reference order
+    pub contigs: Vec<(String, usize)>,
+
+    pub substitutions: Vec<Substitution>,
+
+    /// Records that could not be compared, with the reason: reference
+    /// records first, then query records missing from the reference
+    pub skipped: Vec<(String, String)>,
+}
+
+impl Diff {
+    /// Compares each reference record with the query record of same ID
+    pub fn new(
+        reference_header: &Header,
+        references: &[Record],
+        query_header: &Header,
+        queries: &[Record],
+    ) -> Self {
+        let by_id: HashMap<&str, &Record> = queries
+            .iter()
+            .map(|record| (record.seq_id.as_str(), record))
+            .collect();
+        let results: Vec<_> = references
+            .par_iter()
+            .map(|reference| {
+                let query = by_id
+                    .get(reference.seq_id.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("missing from the query"))?;
+                let found = substitutions(reference_header, reference, query_header, query)?;
+                let length = reference.tri_integers.sequence_len(reference.overlap);
+                Ok::<_, anyhow::Error>((length, found))
+            })
+            .collect();
+
+        let mut diff = Diff {
+            contigs: Vec::new(),
+            substitutions: Vec::new(),
+            skipped: Vec::new(),
+        };
+        for (reference, result) in references.iter().zip(results) {
+            match result {
+                Ok((length, found)) => {
+                    diff.contigs.push((reference.seq_id.clone(), length));
+                    diff.substitutions.extend(found);
+                }
+                Err(e) => diff.skipped.push((reference.seq_id.clone(), e.to_string())),
+            }
+        }
+
+        let reference_ids: HashSet<&str> = references.iter().map(|r| r.seq_id.as_str()).collect();
+        diff.skipped.extend(
+            queries
+                .iter()
+                .filter(|query| !reference_ids.contains(query.seq_id.as_str()))
+                .map(|query| {
+                    (
+                        query.seq_id.clone(),
+                        "missing from the reference".to_string(),
+                    )
+                }),
+        );
+        diff
+    }
+
+    /// Writes the substitutions as a VCF file
+    pub fn write_all<W: Write>(&self, mut writer: W, reference: &str) -> io::Result<()> {
+        writeln!(writer, "##fileformat=VCFv4.2")?;
+        writeln!(writer, "##source=chaoscoder diff")?;
+        writeln!(writer, "##reference={reference}")?;
+        for (id, length) in &self.contigs {
+            writeln!(writer, "##contig=<ID={id},length={length}>")?;
+        }
+        writeln!(writer, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+        for substitution in &self.substitutions {
+            writeln!(writer, "{substitution}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bicgr::Molecule;
+    use crate::icgr::ChaosEncoder;
+
+    fn record(id: &str, sequence: &[u8], block_length: usize, header: &Header) -> Record {
+        Record {
+            seq_id: id.to_string(),
+            desc: None,
+            overlap: 3,
+            tri_integers: sequence
+                .encode(block_length, 3, false, &header.encoding_alphabet().unwrap())
+                .unwrap(),
+        }
+    }
+
+    fn mutate(sequence: &[u8], changes: &[(usize, u8)]) -> Vec<u8> {
+        let mut mutated = sequence.to_vec();
+        for &(position, symbol) in changes {
+            mutated[position] = symbol;
+        }
+        mutated
+    }
+
+    #[test]
+    fn test_substitutions_from_integers() {
+        let header = Header::default();
+        let reference = b"ACGTTGCAAGCTTACGGATCCAGTACGATCGATTACAGGCATGCA";
+        // Position 9 is in the overlap of the first two blocks
+        let query = mutate(reference, &[(0, b'T'), (9, b'A'), (30, b'C'), (44, b'G')]);
+        let found = substitutions(
+            &header,
+            &record("s1", reference, 10, &header),
+            &header,
+            &record("s1", &query, 10, &header),
+        )
+        .unwrap();
+        let positions: Vec<(usize, u8, u8)> = found
+            .iter()
+            .map(|s| (s.position, s.reference, s.alternative))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (0, b'A', b'T'),
+                (9, b'G', b'A'),
+                (30, b'G', b'C'),
+                (44, b'A', b'G')
+            ]
+        );
+        assert_eq!(found[1].to_string(), "s1\t10\t.\tG\tA\t.\t.\t.");
+    }
+
+    #[test]
+    fn test_unknown_symbols() {
+        let header = Header::default();
+        let known = record("s1", b"ACGTTGCAAGCTTACGGATC", 10, &header);
+        // The second block starts with the N at position 7
+        let unknown = record("s2", b"ACGTTGCNAGCTTACGGATC", 10, &header);
+        assert_eq!(unknown_symbols(std::slice::from_ref(&known)), None);
+        assert_eq!(unknown_symbols(&[known, unknown]), Some("s2"));
+    }
+
+    #[test]
+    fn test_substitutions_with_decoding() {
+        let header = Header::default();
+        let reference = b"ACGTTGCAAGCTTACGGATCCAGTACGATCGATTACAGGCATGCA";
+        let query = mutate(reference, &[(5, b'A'), (20, b'G')]);
+        let expected = vec![(5, b'G', b'A'), (20, b'C', b'G')];
+        let positions = |found: Vec<Substitution>| -> Vec<(usize, u8, u8)> {
+            found
+                .iter()
+                .map(|s| (s.position, s.reference, s.alternative))
+                .collect()
+        };
+
+        // Different blocks
+        let found = substitutions(
+            &header,
+            &record("s1", reference, 10, &header),
+            &header,
+            &record("s1", &query, 20, &header),
+        )
+        .unwrap();
+        assert_eq!(positions(found), expected);
+
+        // Different alphabets, and RNA against DNA
+        let swapped = Header {
+            alphabet: "T,A,G,C".parse().unwrap(),
+            molecule: Molecule::Rna,
+        };
+        let rna: Vec<u8> = query
+            .iter()
+            .map(|&b| if b == b'T' { b'U' } else { b })
+            .collect();
+        let found = substitutions(
+            &header,
+            &record("s1", reference, 10, &header),
+            &swapped,
+            &record("s1", &rna, 10, &swapped),
+        )
+        .unwrap();
+        assert_eq!(positions(found), expected);
+
+        let shorter = record("s1", &reference[1..], 10, &header);
+        assert!(substitutions(
+            &header,
+            &record("s1", reference, 10, &header),
+            &header,
+            &shorter
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_diff_write_all() {
+        let header = Header::default();
+        let references = vec![
+            record("s1", b"ACGTACGTACGT", 6, &header),
+            record("s2", b"GGGG", 6, &header),
+        ];
+        let queries = vec![
+            record("s3", b"ACGT", 6, &header),
+            record("s1", b"ACGTACCTACGT", 6, &header),
+        ];
+        let diff = Diff::new(&header, &references, &header, &queries);
+        assert_eq!(
+            diff.skipped,
+            vec![
+                ("s2".to_string(), "missing from the query".to_string()),
+                ("s3".to_string(), "missing from the reference".to_string())
+            ]
+        );
+
+        let mut output = Vec::new();
+        diff.write_all(&mut output, "ref.bicgr").unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("##contig=<ID=s1,length=12>\n"));
+        assert!(output.ends_with("\ns1\t7\t.\tG\tC\t.\t.\t.\n"));
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use dssim_core::Dssim;
+
+use crate::fcgr::Fcgr;
+use crate::render::{self, ColourMap};
+
+/// Signed difference between the k-mer frequencies of two FCGRs
+#[derive(Debug, Clone, PartialEq)]
+pub struct FcgrDifference {
+    query: Fcgr,
+    reference: Fcgr,
+
+    /// Query minus reference frequency of each cell, row-major
+    values: Vec<f64>,
+}
+
+impl FcgrDifference {
+    /// Subtracts the normalised reference FCGR from the normalised query FCGR
+    pub fn new(query: Fcgr, reference: Fcgr) -> anyhow::Result<Self> {
+        if query.side() != reference.side() {
+            anyhow::bail!("Cannot subtract FCGRs of different depths");
+        }
+        let (tq, tr) = (query.total().max(1) as f64, reference.total().max(1) as f64);
+        let values = query
+            .counts()
+            .iter()
+            .zip(reference.counts())
+            .map(|(&q, &r)| q as f64 / tq - r as f64 / tr)
+            .collect();
+        Ok(FcgrDifference {
+            query,
+            reference,
+            values,
+        })
+    }
+
+    /// Cells sorted by decreasing absolute difference, ties by cell order
+    pub fn ranked(&self) -> Vec<usize> {
+        let mut cells: Vec<usize> = (0..self.values.len()).collect();
+        cells.sort_by(|&a, &b| self.values[b].abs().total_cmp(&self.values[a].abs()));
+        cells
+    }
+
+    /// Writes the `n` most differing k-mers as TSV.
+    ///
+    /// Positive differences are k-mers over-represented in the query.
+    pub fn write_top<W: Write>(&self, mut writer: W, n: usize) -> io::Result<()> {
+        writeln!(
+            writer,
+            "#kmer\tquery_count\treference_count\tquery_frequency\treference_frequency\tdifference"
+        )?;
+        let (tq, tr) = (
+            self.query.total().max(1) as f64,
+            self.reference.total().max(1) as f64,
+        );
+        for cell in self.ranked().into_iter().take(n) {
+            let (q, r) = (self.query.counts()[cell], self.reference.counts()[cell]);
+            writeln!(
+                writer,
+                "{}\t{q}\t{r}\t{:.6}\t{:.6}\t{:+.6}",
+                self.query.kmer(cell),
+                q as f64 / tq,
+                r as f64 / tr,
+                self.values[cell]
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Draws the difference as a PNG heatmap, each cell being a square of
+    /// pixels so that the image is at most `size` pixels wide.
+    ///
+    /// The colour map is centred on zero and scaled to the largest absolute
+    /// difference.
+    ///
+    /// # Errors
+    /// Returns an error if `size` is smaller than the 2^k cells of a side.
+    pub fn write_image(&self, path: &Path, size: u32, colour_map: ColourMap) -> anyhow::Result<()> {
+        let side = self.query.side();
+        if (size as usize) < side {
+            anyhow::bail!(
+                "Image size {size} is smaller than the {side} cells of a side of the FCGR"
+            );
+        }
+        let max = self.values.iter().fold(0.0f64, |m, v| m.max(v.abs()));
+        let colours: Vec<[u8; 3]> = self
+            .values
+            .iter()
+            .map(|&v| {
+                let scaled = if max > 0.0 { v / max } else { 0.0 };
+                let c = colour_map.colour((scaled + 1.0) / 2.0);
+                [c.0, c.1, c.2]
+            })
+            .collect();
+        render::write_cells(path, &colours, side, size)
+    }
+
+    /// Writes DSSIM's per-pixel similarity map between the FCGR images as a
+    /// grayscale PNG, white where they are identical, and returns the DSSIM.
+    pub fn write_dssim_map(&self, path: &Path, size: u32) -> anyhow::Result<f64> {
+        let mut dssim = Dssim::new();
+        dssim.set_save_ssim_maps(1);
+        let (value, maps) = dssim.compare(&self.query.to_image(), self.reference.to_image());
+        let map = maps
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("DSSIM did not produce a similarity map"))?;
+
+        let side = map.map.width();
+        let colours: Vec<[u8; 3]> = map
+            .map
+            .pixels()
+            .map(|ssim| {
+                let grey = (ssim.clamp(0.0, 1.0) * 255.0).round() as u8;
+                [grey; 3]
+            })
+            .collect();
+        render::write_cells(path, &colours, side, size)?;
+        Ok(value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn difference() -> FcgrDifference {
+        FcgrDifference::new(
+            Fcgr::from_sequence(b"AAAAC", 1),
+            Fcgr::from_sequence(b"ACGTT", 1),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_difference_values() {
+        let diff = difference();
+        // Cells are T, A, C, G
+        for (value, expected) in diff.values.iter().zip([-0.4, 0.6, 0.0, -0.2]) {
+            assert!((value - expected).abs() < 1e-12);
+        }
+        assert_eq!(diff.ranked(), vec![1, 0, 3, 2]);
+        assert!(FcgrDifference::new(Fcgr::new(1), Fcgr::new(2)).is_err());
+    }
+
+    #[test]
+    fn test_write_top() {
+        let mut output = Vec::new();
+        difference().write_top(&mut output, 2).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "A\t4\t1\t0.800000\t0.200000\t+0.600000");
+        assert_eq!(lines[2], "T\t0\t2\t0.000000\t0.400000\t-0.400000");
+    }
+
+    #[test]
+    fn test_write_image_and_dssim_map() {
+        let dir = tempdir().unwrap();
+        let diff = FcgrDifference::new(
+            Fcgr::from_sequence(b"ACGTACGGATTACCAGTTAGGCATTAGACA", 3),
+            Fcgr::from_sequence(b"TTTTTGGGCCAAACCCGGGATATATCGCGC", 3),
+        )
+        .unwrap();
+
+        let image = dir.path().join("diff.png");
+        diff.write_image(&image, 100, ColourMap::Diverging).unwrap();
+        let decoded = image::open(&image).unwrap().to_rgb8();
+        // 8 cells of 12 pixels
+        assert_eq!(decoded.dimensions(), (96, 96));
+        assert!(diff.write_image(&image, 7, ColourMap::Diverging).is_err());
+
+        let map = dir.path().join("map.png");
+        let dssim = diff.write_dssim_map(&map, 64).unwrap();
+        assert!(dssim > 0.0);
+        assert!(map.exists());
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use noodles::fasta;
+use rayon::prelude::*;
+
+use crate::fcgr::Fcgr;
+use crate::render::{self, ColourMap};
+use crate::scan;
+
+/// Largest distance between two k-mer frequency vectors, reached when they
+/// share no k-mer
+const MAX_DISTANCE: f64 = std::f64::consts::SQRT_2;
+
+/// Window of a record with its FCGR signature
+#[derive(Debug, Clone)]
+pub struct Window {
+    /// Record ID
+    pub seqid: String,
+
+    /// 0-based start position
+    pub start: usize,
+
+    /// 0-based end position, exclusive
+    pub end: usize,
+
+    signature: Fcgr,
+}
+
+/// Computes the FCGR signatures of windows of `window` bases every `step`
+/// bases along each record
+pub fn windows(records: &[fasta::Record], k: u8, window: usize, step: usize) -> Vec<Window> {
+    scan::map_windows(records, k, window, step, |record, start, end, signature| {
+        Window {
+            seqid: record.name().to_string(),
+            start,
+            end,
+            signature,
+        }
+    })
+}
+
+/// Similarity matrix between the windows of two genomes, an alignment-free
+/// dotplot
+#[derive(Debug)]
+pub struct Dotplot {
+    /// Windows of the query genome, one per row
+    pub query: Vec<Window>,
+
+    /// Windows of the reference genome, one per column
+    pub reference: Vec<Window>,
+
+    /// Similarity of each pair of windows, row-major
+    values: Vec<f64>,
+}
+
+impl Dotplot {
+    /// Compares every query window with every reference window.
+    ///
+    /// The similarity is one minus the distance between the FCGRs scaled to
+    /// [0, 1], so 1 for windows with identical k-mer frequencies and 0 for
+    /// windows sharing no k-mer.
+    pub fn new(query: Vec<Window>, reference: Vec<Window>) -> Self {
+        let values = query
+            .par_iter()
+            .flat_map_iter(|q| {
+                reference
+                    .iter()
+                    .map(|r| 1.0 - q.signature.distance(&r.signature) / MAX_DISTANCE)
+            })
+            .collect();
+        Dotplot {
+            query,
+            reference,
+            values,
+        }
+    }
+
+    /// Similarity between a query window and a reference window
+    pub fn similarity(&self, query: usize, reference: usize) -> f64 {
+        self.values[query * self.reference.len() + reference]
+    }
+
+    /// Draws the matrix as a PNG, query windows from top to bottom and
+    /// reference windows from left to right, each window pair being a square
+    /// of pixels so that the image is at most `size` pixels wide and high.
+    ///
+    /// With more windows than pixels, blocks of windows are binned into one
+    /// pixel taking their highest similarity, so that matching regions remain
+    /// visible. Colours are stretched between the lowest and the highest
+    /// similarity.
+    pub fn write_image(&self, path: &Path, size: u32, colour_map: ColourMap) -> anyhow::Result<()> {
+        let (rows, columns) = (self.query.len(), self.reference.len());
+        let bin = rows.max(columns).div_ceil(size.max(1) as usize).max(1);
+        let (binned_rows, binned_columns) = (rows.div_ceil(bin), columns.div_ceil(bin));
+        let mut values = vec![f64::NEG_INFINITY; binned_rows * binned_columns];
+        for (i, row) in self.values.chunks(columns.max(1)).enumerate() {
+            for (j, &value) in row.iter().enumerate() {
+                let cell = &mut values[i / bin * binned_columns + j / bin];
+                *cell = cell.max(value);
+            }
+        }
+
+        let (min, max) = values
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+                (lo.min(v), hi.max(v))
+            });
+        let range = max - min;
+        let colours: Vec<[u8; 3]> = values
+            .iter()
+            .map(|&v| {
+                let c = colour_map.colour(if range > 0.0 { (v - min) / range } else { 1.0 });
+                [c.0, c.1, c.2]
+            })
+            .collect();
+        render::write_cells(path, &colours, binned_columns, size)
+    }
+
+    /// Writes the similarity of every pair of windows as TSV
+    pub fn write_all<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "#query\tquery_start\tquery_end\treference\treference_start\treference_end\tsimilarity"
+        )?;
+        for (i, q) in self.query.iter().enumerate() {
+            for (j, r) in self.reference.iter().enumerate() {
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{:.6}",
+                    q.seqid,
+                    q.start,
+                    q.end,
+                    r.seqid,
+                    r.start,
+                    r.end,
+                    self.similarity(i, j)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::record;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_dotplot_follows_rearrangement() {
+        let blocks: [&[u8]; 3] = [b"AAAACAAAAC", b"GCGCGGCGCG", b"ATTATTTATA"];
+        let query = blocks.concat();
+        // Reference with the first and last blocks swapped
+        let reference = [blocks[2], blocks[1], blocks[0]].concat();
+        let dotplot = Dotplot::new(
+            windows(&[record("q", &query)], 2, 10, 10),
+            windows(&[record("r", &reference)], 2, 10, 10),
+        );
+        assert_eq!((dotplot.query.len(), dotplot.reference.len()), (3, 3));
+        for (q, r) in [(0, 2), (1, 1), (2, 0)] {
+            assert!((dotplot.similarity(q, r) - 1.0).abs() < 1e-12);
+        }
+        assert!(dotplot.similarity(0, 0) < 0.5);
+
+        let mut output = Vec::new();
+        dotplot.write_all(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 10);
+        assert_eq!(output.lines().nth(3), Some("q\t0\t10\tr\t20\t30\t1.000000"));
+    }
+
+    #[test]
+    fn test_write_image() {
+        let dir = tempdir().unwrap();
+        let dotplot = Dotplot::new(
+            windows(&[record("q", b"ACGTACGTAAAAAAAAGGCC")], 1, 4, 4),
+            windows(&[record("r", b"ACGTTTTTCCCC")], 1, 4, 4),
+        );
+        let path = dir.path().join("dotplot.png");
+        dotplot.write_image(&path, 100, ColourMap::Viridis).unwrap();
+        // 5 x 3 windows of 20 pixels
+        let decoded = image::open(&path).unwrap().to_rgb8();
+        assert_eq!(decoded.dimensions(), (60, 100));
+
+        // More windows than pixels are binned by 2 x 2 windows
+        dotplot.write_image(&path, 4, ColourMap::Viridis).unwrap();
+        let decoded = image::open(&path).unwrap().to_rgb8();
+        assert_eq!(decoded.dimensions(), (2, 3));
+        // Binned AAAA query windows share no k-mer with the CCCC window
+        let (best, worst) = (
+            ColourMap::Viridis.colour(1.0),
+            ColourMap::Viridis.colour(0.0),
+        );
+        assert_eq!(decoded.get_pixel(0, 0).0, [best.0, best.1, best.2]);
+        assert_eq!(decoded.get_pixel(1, 1).0, [worst.0, worst.1, worst.2]);
+    }
+}
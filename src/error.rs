@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::icgr::MAX_BLOCK_LENGTH;
+
 #[derive(Debug, Error)]
 pub enum IcgrError {
     #[error("UTF-8 decoding failed: {0}")]
@@ -22,4 +24,19 @@ pub enum IcgrError {
 
     #[error("Unknown nucleotide encountered: {0}")]
     UnknownNucleotide(char),
+
+    #[error("iCGR needs an alphabet of 4 corners, got {0}")]
+    UnsupportedAlphabet(String),
+
+    #[error("Alphabet {0} does not put A, C, G and T on their own corners")]
+    NoComplement(String),
+
+    #[error("Invalid iCGR integers: {0}")]
+    InvalidTriIntegers(String),
+
+    #[error("iCGR blocks hold at most {MAX_BLOCK_LENGTH} symbols, got {0}")]
+    BlockTooLong(usize),
+
+    #[error("Position {position} is out of a sequence of length {length}")]
+    OutOfBounds { position: usize, length: usize },
 }
@@ -0,0 +1,344 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::collections::VecDeque;
+use std::iter::Peekable;
+
+use dssim_core::{Dssim, DssimImage};
+use imgref::ImgVec;
+use serde::{Deserialize, Serialize};
+
+use crate::alphabet::Alphabet;
+use crate::icgr::{self, RollingIcgr};
+
+/// Frequency Chaos Game Representation (FCGR) ---------------------------------
+///
+/// The CGR square is divided into a 2^k x 2^k grid where each cell corresponds
+/// to one k-mer. Cells are stored row-major, with row 0 at the top of the CGR
+/// square (y = 1) and column 0 on its left side (x = -1), so that the grid
+/// matches the drawn CGR image.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Fcgr {
+    /// k-mer length, the grid has 2^k cells per side
+    k: u8,
+
+    /// Number of k-mers falling in each cell
+    counts: Vec<u64>,
+}
+
+impl Fcgr {
+    /// Creates an empty FCGR of depth k
+    pub fn new(k: u8) -> Self {
+        let side = 1usize << k;
+        Fcgr {
+            k,
+            counts: vec![0; side * side],
+        }
+    }
+
+    /// Creates an FCGR from already computed counts
+    pub fn from_counts(k: u8, counts: Vec<u64>) -> anyhow::Result<Self> {
+        let side = 1usize << k;
+        if counts.len() != side * side {
+            anyhow::bail!(
+                "FCGR of depth {} needs {} cells, got {}",
+                k,
+                side * side,
+                counts.len()
+            );
+        }
+        Ok(Fcgr { k, counts })
+    }
+
+    /// Counts the k-mers of a sequence into a new FCGR
+    pub fn from_sequence(sequence: &[u8], k: u8) -> Self {
+        let mut fcgr = Fcgr::new(k);
+        fcgr.add_sequence(sequence);
+        fcgr
+    }
+
+    /// Adds the k-mers of a sequence to the FCGR.
+    ///
+    /// U counts as T, and k-mers containing a symbol other than A, C, G, T or
+    /// U are skipped.
+    pub fn add_sequence(&mut self, sequence: &[u8]) {
+        let kmers = RollingIcgr::new(sequence, self.k as usize, &Alphabet::nucleic_acids())
+            .expect("FCGR depths are valid iCGR windows");
+        for (_, x, y) in kmers {
+            let cell = self.cell(x, y);
+            self.counts[cell] += 1;
+        }
+    }
+
+    /// FCGRs of successive windows of a sequence, given as (start, end) pairs
+    /// with nondecreasing starts and ends.
+    ///
+    /// The k-mers are rolled along the sequence once: each window adds the
+    /// k-mers entering it and removes those leaving the previous window.
+    pub fn windows<'a>(
+        sequence: &'a [u8],
+        k: u8,
+        windows: &'a [(usize, usize)],
+    ) -> SlidingFcgr<'a> {
+        let offset = windows.first().map_or(0, |&(start, _)| start);
+        let kmers = RollingIcgr::new(
+            &sequence[offset.min(sequence.len())..],
+            k as usize,
+            &Alphabet::nucleic_acids(),
+        )
+        .expect("FCGR depths are valid iCGR windows");
+        SlidingFcgr {
+            fcgr: Fcgr::new(k),
+            kmers: kmers.peekable(),
+            offset,
+            window_kmers: VecDeque::new(),
+            windows: windows.iter(),
+        }
+    }
+
+    /// Cell of a k-mer given its iCGR integers: x is positive on A and G,
+    /// the columns of bit 1, and y on A and T, the rows of bit 0
+    fn cell(&self, x: i128, y: i128) -> usize {
+        let mask = (1u128 << self.k) - 1;
+        let bits = |value: i128| ((value as u128).wrapping_add(mask) >> 1) as usize;
+        let (row, col) = (!bits(y) & (self.side() - 1), bits(x));
+        row * self.side() + col
+    }
+
+    /// Combines the FCGR with the FCGR of the reverse complement of its
+    /// sequences, so that both strands of a sequence give the same signature.
+    ///
+    /// Each cell counts its k-mer and the reverse complement of the k-mer.
+    /// Reversing the k-mer reverses the order of the bits, and complementing
+    /// a nucleotide flips its column bit or its row bit when the complement
+    /// negates x or y.
+    pub fn canonical(&self) -> Fcgr {
+        let (sx, sy) = icgr::complement_signs(&Alphabet::nucleic_acids())
+            .expect("A, C, G and T have a corner each");
+        let side = self.side();
+        let shift = usize::BITS - u32::from(self.k);
+        let flip = |sign: i128| if sign < 0 { side - 1 } else { 0 };
+        let reverse = |bits: usize| bits.reverse_bits() >> shift;
+        let counts = (0..self.counts.len())
+            .map(|index| {
+                let (row, col) = (index / side, index % side);
+                let rc = (reverse(row) ^ flip(sy)) * side + (reverse(col) ^ flip(sx));
+                self.counts[index] + self.counts[rc]
+            })
+            .collect();
+        Fcgr { k: self.k, counts }
+    }
+
+    /// Number of cells per side of the grid
+    pub fn side(&self) -> usize {
+        1usize << self.k
+    }
+
+    /// k-mer of a cell, given its row-major index
+    pub fn kmer(&self, index: usize) -> String {
+        let alphabet = Alphabet::nucleic_acids();
+        let side = self.side();
+        let (row, col) = (index / side, index % side);
+        // The lowest bit holds the oldest nucleotide, i.e. the first of the
+        // k-mer. Columns of bit 1 are on the right (x = 1) and rows of bit 1
+        // at the bottom (y = -1).
+        let sign = |bits: usize, bit: u8| if (bits >> bit) & 1 == 1 { 1 } else { -1 };
+        (0..self.k)
+            .map(|bit| {
+                alphabet
+                    .symbol_at((sign(col, bit), -sign(row, bit)))
+                    .expect("nucleotides have a symbol on each corner") as char
+            })
+            .collect()
+    }
+
+    /// Raw k-mer counts, row-major
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Total number of counted k-mers
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Euclidean distance between the k-mer frequencies of two FCGRs of same depth
+    pub fn distance(&self, other: &Fcgr) -> f64 {
+        let (t1, t2) = (self.total().max(1) as f64, other.total().max(1) as f64);
+        self.counts
+            .iter()
+            .zip(&other.counts)
+            .map(|(&a, &b)| {
+                let d = a as f64 / t1 - b as f64 / t2;
+                d * d
+            })
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    /// Converts the FCGR to a grayscale image for DSSIM comparison.
+    ///
+    /// As in drawn CGR images, frequent k-mers are dark on a white background.
+    pub fn to_image(&self) -> DssimImage<f32> {
+        let max = self.counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let pixels = self.counts.iter().map(|&c| 1.0 - c as f32 / max).collect();
+        Dssim::new()
+            .create_image(&ImgVec::new(pixels, self.side(), self.side()))
+            .expect("infallible")
+    }
+}
+
+/// Iterator over the FCGRs of windows of a sequence, see [`Fcgr::windows`]
+pub struct SlidingFcgr<'a> {
+    /// Counts of the current window
+    fcgr: Fcgr,
+
+    /// k-mers not yet in a window, positioned from `offset`
+    kmers: Peekable<RollingIcgr<'a>>,
+
+    offset: usize,
+
+    /// Start and cell of the k-mers of the current window
+    window_kmers: VecDeque<(usize, usize)>,
+
+    windows: std::slice::Iter<'a, (usize, usize)>,
+}
+
+impl Iterator for SlidingFcgr<'_> {
+    type Item = Fcgr;
+
+    fn next(&mut self) -> Option<Fcgr> {
+        let &(start, end) = self.windows.next()?;
+        while let Some(&(kmer_start, cell)) = self.window_kmers.front() {
+            if kmer_start >= start {
+                break;
+            }
+            self.fcgr.counts[cell] -= 1;
+            self.window_kmers.pop_front();
+        }
+        let k = self.fcgr.k as usize;
+        while let Some(&(kmer_start, x, y)) = self.kmers.peek() {
+            let kmer_start = kmer_start + self.offset;
+            if kmer_start + k > end {
+                break;
+            }
+            self.kmers.next();
+            if kmer_start >= start {
+                let cell = self.fcgr.cell(x, y);
+                self.fcgr.counts[cell] += 1;
+                self.window_kmers.push_back((kmer_start, cell));
+            }
+        }
+        Some(self.fcgr.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kmer_of_cell() {
+        for kmer in ["A", "GT", "ACG", "TTAGC"] {
+            let fcgr = Fcgr::from_sequence(kmer.as_bytes(), kmer.len() as u8);
+            let cell = fcgr.counts().iter().position(|&c| c == 1).unwrap();
+            assert_eq!(fcgr.kmer(cell), kmer);
+        }
+    }
+
+    #[test]
+    fn test_fcgr_cells_follow_cgr_quadrants() {
+        // A is the top right corner, T top left, C bottom left and G bottom right
+        assert_eq!(Fcgr::from_sequence(b"A", 1).counts(), &[0, 1, 0, 0]);
+        assert_eq!(Fcgr::from_sequence(b"T", 1).counts(), &[1, 0, 0, 0]);
+        assert_eq!(Fcgr::from_sequence(b"C", 1).counts(), &[0, 0, 1, 0]);
+        assert_eq!(Fcgr::from_sequence(b"G", 1).counts(), &[0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_fcgr_last_nucleotide_is_quadrant() {
+        // "TA" ends in A, so it lies in the top right quadrant,
+        // in the top left sub-cell of it (T)
+        let fcgr = Fcgr::from_sequence(b"TA", 2);
+        assert_eq!(fcgr.counts().iter().sum::<u64>(), 1);
+        assert_eq!(fcgr.counts()[2], 1);
+    }
+
+    #[test]
+    fn test_fcgr_skips_unknown_nucleotides() {
+        let fcgr = Fcgr::from_sequence(b"ACNGTa", 2);
+        assert_eq!(fcgr.counts().iter().sum::<u64>(), 3);
+    }
+
+    #[test]
+    fn test_fcgr_canonical() {
+        let sequence = b"ACGGTTACAGGATTACCAGGAAT";
+        let reverse = crate::utils::reverse_complement(sequence);
+        for k in 1..=4 {
+            let forward = Fcgr::from_sequence(sequence, k);
+            let backward = Fcgr::from_sequence(&reverse, k);
+            let canonical = forward.canonical();
+            assert_eq!(canonical, backward.canonical(), "k={k}");
+            // Each cell counts its k-mer on both strands
+            for (index, &count) in canonical.counts().iter().enumerate() {
+                let kmer = forward.kmer(index);
+                let rc = crate::utils::reverse_complement(kmer.as_bytes());
+                let rc_index = Fcgr::from_sequence(&rc, k)
+                    .counts()
+                    .iter()
+                    .position(|&c| c == 1)
+                    .unwrap();
+                let expected = forward.counts()[index] + forward.counts()[rc_index];
+                assert_eq!(count, expected, "{kmer}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_fcgr_rna_matches_dna() {
+        assert_eq!(
+            Fcgr::from_sequence(b"ACGUUGCAu", 3),
+            Fcgr::from_sequence(b"ACGTTGCAT", 3)
+        );
+    }
+
+    #[test]
+    fn test_sliding_windows_match_fcgr() {
+        let sequence = b"ACGGTTACAGGATTACCAGGAATNNCGATCGGATCCAGTAGGCATACGTTAGCAuugcaCCGATGA";
+        let windows = [
+            (0, 10),
+            (3, 20),
+            (3, 25),
+            (30, 40),
+            (38, 50),
+            (59, 66),
+            (60, 66),
+        ];
+        for k in 1..=4 {
+            let sliding: Vec<Fcgr> = Fcgr::windows(sequence, k, &windows).collect();
+            let expected: Vec<Fcgr> = windows
+                .iter()
+                .map(|&(start, end)| Fcgr::from_sequence(&sequence[start..end], k))
+                .collect();
+            assert_eq!(sliding, expected, "k={k}");
+        }
+        assert_eq!(Fcgr::windows(sequence, 3, &[]).count(), 0);
+    }
+
+    #[test]
+    fn test_fcgr_distance() {
+        let a = Fcgr::from_sequence(b"AAAA", 1);
+        let b = Fcgr::from_sequence(b"AACC", 1);
+        assert_eq!(a.distance(&a), 0.0);
+        assert!((a.distance(&b) - 0.5_f64.sqrt()).abs() < 1e-12);
+        assert_eq!(a.distance(&b), b.distance(&a));
+    }
+
+    #[test]
+    fn test_fcgr_from_counts_size() {
+        assert!(Fcgr::from_counts(1, vec![1, 2, 3, 4]).is_ok());
+        assert!(Fcgr::from_counts(1, vec![1, 2, 3]).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rayon::prelude::*;
+
+use crate::bicgr::{Header, Record};
+use crate::utils;
+
+/// Bases matched by a nucleotide or an IUPAC code, one bit per base in the
+/// order A, C, G, T. U matches as T.
+fn iupac_mask(symbol: u8) -> Option<u8> {
+    let (a, c, g, t) = (1, 2, 4, 8);
+    Some(match symbol.to_ascii_uppercase() {
+        b'A' => a,
+        b'C' => c,
+        b'G' => g,
+        b'T' | b'U' => t,
+        b'R' => a | g,
+        b'Y' => c | t,
+        b'S' => c | g,
+        b'W' => a | t,
+        b'K' => g | t,
+        b'M' => a | c,
+        b'B' => c | g | t,
+        b'D' => a | g | t,
+        b'H' => a | c | t,
+        b'V' => a | c | g,
+        b'N' => a | c | g | t,
+        _ => return None,
+    })
+}
+
+/// Nucleotide motif, possibly with IUPAC codes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    motif: String,
+
+    /// Bases matched at each position of the motif
+    masks: Vec<u8>,
+}
+
+impl Pattern {
+    /// Reverse complement of the motif
+    pub fn reverse_complement(&self) -> Pattern {
+        let motif = utils::reverse_complement(self.motif.as_bytes());
+        Pattern {
+            masks: motif.iter().map(|&b| iupac_mask(b).unwrap_or(0)).collect(),
+            motif: String::from_utf8_lossy(&motif).into_owned(),
+        }
+    }
+
+    /// Whether the motif matches the start of a sequence given as base masks.
+    /// Unknown bases, of mask 0, match no position of the motif.
+    fn matches(&self, sequence: &[u8]) -> bool {
+        self.masks
+            .iter()
+            .zip(sequence)
+            .all(|(&pattern, &base)| pattern & base != 0)
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err("Pattern is empty".to_string());
+        }
+        let masks = s
+            .bytes()
+            .map(|b| iupac_mask(b).ok_or(format!("Invalid IUPAC code {} in pattern", b as char)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Pattern {
+            motif: s.to_ascii_uppercase(),
+            masks,
+        })
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.motif)
+    }
+}
+
+/// Occurrence of a pattern in a record
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    /// Record ID
+    pub seq_id: String,
+
+    /// 0-based start position
+    pub start: usize,
+
+    /// 0-based end position, exclusive
+    pub end: usize,
+
+    /// '+' for the pattern, '-' for its reverse complement
+    pub strand: char,
+
+    /// Motif searched
+    pub pattern: String,
+}
+
+impl fmt::Display for Match {
+    /// Formats the match as a BED6 line named after the pattern
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t0\t{}",
+            self.seq_id, self.start, self.end, self.pattern, self.strand
+        )
+    }
+}
+
+/// Finds every occurrence of a pattern in a record, overlapping ones
+/// included, on both strands when `reverse_complement`.
+///
+/// Blocks are decoded one at a time. The first `overlap` symbols of a block
+/// repeat the end of the previous one and are dropped, and the last symbols
+/// of the previous blocks are kept so that matches spanning two blocks are
+/// found once.
+pub fn find(
+    header: &Header,
+    record: &Record,
+    pattern: &Pattern,
+    reverse_complement: bool,
+) -> Vec<Match> {
+    let mut patterns = vec![('+', pattern.clone())];
+    let reverse = pattern.reverse_complement();
+    // Palindromic motifs would be reported twice
+    if reverse_complement && reverse.masks != pattern.masks {
+        patterns.push(('-', reverse));
+    }
+
+    let length = pattern.masks.len();
+    let overlap = record.overlap as usize;
+    let mut found = Vec::new();
+    // Bases not yet tried as a match start, from position `offset`
+    let mut pending: Vec<u8> = Vec::new();
+    let mut offset = 0;
+    for (i, block) in record.tri_integers.iter().enumerate() {
+        let symbols = header.decode_block(block);
+        let shared = if i == 0 {
+            0
+        } else {
+            overlap.min(symbols.len())
+        };
+        pending.extend(
+            symbols[shared..]
+                .iter()
+                .map(|&b| iupac_mask(b).filter(|m| m.is_power_of_two()).unwrap_or(0)),
+        );
+        if pending.len() < length {
+            continue;
+        }
+
+        let starts = pending.len() - length + 1;
+        for start in 0..starts {
+            for (strand, pattern) in &patterns {
+                if pattern.matches(&pending[start..]) {
+                    found.push(Match {
+                        seq_id: record.seq_id.clone(),
+                        start: offset + start,
+                        end: offset + start + length,
+                        strand: *strand,
+                        pattern: pattern.motif.clone(),
+                    });
+                }
+            }
+        }
+        pending.drain(..starts);
+        offset += starts;
+    }
+    found
+}
+
+/// Finds the occurrences of a pattern in each record, in record order
+pub fn find_all(
+    header: &Header,
+    records: &[Record],
+    pattern: &Pattern,
+    reverse_complement: bool,
+) -> Vec<Match> {
+    records
+        .par_iter()
+        .flat_map_iter(|record| find(header, record, pattern, reverse_complement))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bicgr::Molecule;
+    use crate::icgr::ChaosEncoder;
+
+    fn record(sequence: &[u8], block_length: usize, overlap: u8, header: &Header) -> Record {
+        Record {
+            seq_id: "s1".to_string(),
+            desc: None,
+            overlap,
+            tri_integers: sequence
+                .encode(
+                    block_length,
+                    overlap,
+                    false,
+                    &header.encoding_alphabet().unwrap(),
+                )
+                .unwrap(),
+        }
+    }
+
+    fn positions(matches: &[Match]) -> Vec<(usize, usize, char)> {
+        matches.iter().map(|m| (m.start, m.end, m.strand)).collect()
+    }
+
+    /// Occurrences found on the decoded sequence
+    fn naive(sequence: &[u8], pattern: &Pattern) -> Vec<(usize, usize, char)> {
+        let masks: Vec<u8> = sequence.iter().map(|&b| iupac_mask(b).unwrap()).collect();
+        (0..=sequence.len() - pattern.masks.len())
+            .filter(|&start| pattern.matches(&masks[start..]))
+            .map(|start| (start, start + pattern.masks.len(), '+'))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_across_blocks() {
+        let header = Header::default();
+        let sequence = b"GAATTCAGGATTACAGAATTCTTGAATTCAGATTACAGCGAATTCGATTACAGGAATTC";
+        let pattern: Pattern = "GAATTC".parse().unwrap();
+        let expected = naive(sequence, &pattern);
+        assert_eq!(expected.len(), 5);
+        for (block_length, overlap) in [(100, 1), (10, 3), (7, 1), (6, 5), (4, 2)] {
+            let record = record(sequence, block_length, overlap, &header);
+            let found = find(&header, &record, &pattern, false);
+            assert_eq!(positions(&found), expected, "{block_length} {overlap}");
+        }
+    }
+
+    #[test]
+    fn test_find_overlapping_and_iupac() {
+        let header = Header::default();
+        let sequence = b"AAAAGCATGCTTGTAC";
+        let record = record(sequence, 5, 2, &header);
+
+        let found = find(&header, &record, &"AA".parse().unwrap(), false);
+        assert_eq!(
+            positions(&found),
+            vec![(0, 2, '+'), (1, 3, '+'), (2, 4, '+')]
+        );
+
+        let found = find(&header, &record, &"gcwtgc".parse().unwrap(), false);
+        assert_eq!(positions(&found), vec![(4, 10, '+')]);
+        assert_eq!(found[0].to_string(), "s1\t4\t10\tGCWTGC\t0\t+");
+
+        // GCATGC is its own reverse complement and is reported once, whereas
+        // it also matches GCAWGC, the reverse complement of GCWTGC
+        let found = find(&header, &record, &"GCATGC".parse().unwrap(), true);
+        assert_eq!(positions(&found), vec![(4, 10, '+')]);
+        let found = find(&header, &record, &"GCWTGC".parse().unwrap(), true);
+        assert_eq!(positions(&found), vec![(4, 10, '+'), (4, 10, '-')]);
+
+        assert!("GANTTX".parse::<Pattern>().is_err());
+        assert!("".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn test_find_reverse_strand() {
+        let header = Header {
+            alphabet: "T,A,G,C".parse().unwrap(),
+            molecule: Molecule::Rna,
+        };
+        let sequence = b"CCUUAGGUUACCGGAUCCUAAGCUUAGGC";
+        let record = record(sequence, 8, 3, &header);
+        let found = find(&header, &record, &"TTAGG".parse().unwrap(), true);
+        assert_eq!(
+            positions(&found),
+            vec![(2, 7, '+'), (16, 21, '-'), (23, 28, '+')]
+        );
+        assert_eq!(found[1].pattern, "CCTAA");
+        assert!(find(&header, &record, &"TTAGGG".parse().unwrap(), false).is_empty());
+    }
+}
@@ -9,8 +9,10 @@ use std::ops::Deref;
 use std::str;
 use std::vec::Vec;
 
+use crate::alphabet::Alphabet;
 use crate::error::IcgrError;
 use anyhow::Result;
+use itertools::Itertools;
 use rayon::iter::IntoParallelIterator;
 use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serialize};
@@ -31,7 +33,7 @@ pub struct TriIntegers {
     y: String,
 
     /// Block length
-    n: usize,
+    pub(crate) n: usize,
 }
 
 /// FromParallelIterator trait implementation for TriIntegersList
@@ -109,10 +111,10 @@ impl TriIntegersList {
         self.0.iter()
     }
 
-    pub fn to_dna(&self, overlap: u8) -> Result<String, IcgrError> {
+    pub fn to_dna(&self, overlap: u8, alphabet: &Alphabet) -> Result<String, IcgrError> {
         let dna_chunks: Vec<Vec<u8>> = self
             .iter()
-            .map(|x| tri_integers_to_dna(x.clone()))
+            .map(|x| tri_integers_to_dna(x.clone(), alphabet))
             .collect();
         let chunks: Vec<&[u8]> = dna_chunks.iter().map(|v| v.as_slice()).collect();
         // merge strings with overlaps
@@ -131,6 +133,124 @@ impl TriIntegersList {
     }
 }
 
+/// Sequence operations on blocks overlapping by `overlap` symbols, see the
+/// operations on [`TriIntegers`]. Results may be split into other blocks than
+/// the encoder would use, but decode to the expected sequence.
+impl TriIntegersList {
+    /// Number of symbols of the sequence
+    pub fn sequence_len(&self, overlap: u8) -> usize {
+        let overlaps = overlap as usize * self.len().saturating_sub(1);
+        self.iter()
+            .map(|t| t.n)
+            .sum::<usize>()
+            .saturating_sub(overlaps)
+    }
+
+    /// Position in the sequence of the first symbol of each block
+    fn starts(&self, overlap: u8) -> Vec<usize> {
+        self.iter()
+            .scan(0, |start, block| {
+                let current = *start;
+                *start += block.n.saturating_sub(overlap as usize);
+                Some(current)
+            })
+            .collect()
+    }
+
+    /// The first `length` symbols of the sequence
+    pub fn prefix(&self, length: usize, overlap: u8) -> Result<TriIntegersList, IcgrError> {
+        let total = self.sequence_len(overlap);
+        if length > total {
+            return Err(IcgrError::OutOfBounds {
+                position: length,
+                length: total,
+            });
+        }
+        // The last block kept is the last one adding symbols before `length`
+        let starts = self.starts(overlap);
+        let mut blocks = Vec::new();
+        for (i, (block, &start)) in self.iter().zip(&starts).enumerate() {
+            let shared = if i == 0 { 0 } else { overlap as usize };
+            if start + shared >= length {
+                break;
+            }
+            blocks.push(block.prefix(block.n.min(length - start))?);
+        }
+        Ok(TriIntegersList(blocks))
+    }
+
+    /// The symbols of the sequence from position `start` on
+    pub fn suffix(&self, start: usize, overlap: u8) -> Result<TriIntegersList, IcgrError> {
+        let total = self.sequence_len(overlap);
+        if start > total {
+            return Err(IcgrError::OutOfBounds {
+                position: start,
+                length: total,
+            });
+        }
+        // The first block kept still holds the overlap with the next block
+        // once cut at `start`
+        let starts = self.starts(overlap);
+        let mut blocks = Vec::new();
+        for (i, (block, &block_start)) in self.iter().zip(&starts).enumerate() {
+            let end = block_start + block.n;
+            if blocks.is_empty() {
+                let last = i + 1 == self.len();
+                if end <= start || (!last && end - start < overlap as usize) {
+                    continue;
+                }
+                blocks.push(block.suffix(start.saturating_sub(block_start))?);
+            } else {
+                blocks.push(block.clone());
+            }
+        }
+        Ok(TriIntegersList(blocks))
+    }
+
+    /// The sequence followed by the sequence of `other`.
+    ///
+    /// The last block and the first block of `other` are joined when they fit
+    /// in one block, and otherwise bridged by a block spanning the junction.
+    pub fn concat(
+        &self,
+        other: &TriIntegersList,
+        overlap: u8,
+    ) -> Result<TriIntegersList, IcgrError> {
+        let (Some(last), Some(first)) = (self.last(), other.first()) else {
+            return Ok(if self.is_empty() { other } else { self }.clone());
+        };
+        let overlap = overlap as usize;
+        let mut blocks = self[..self.len() - 1].to_vec();
+        if last.n + first.n <= MAX_BLOCK_LENGTH {
+            blocks.push(last.concat(first)?);
+        } else {
+            if last.n < overlap || first.n < overlap {
+                return Err(IcgrError::ChunkTooShort);
+            }
+            blocks.push(last.clone());
+            if overlap > 0 {
+                blocks.push(
+                    last.suffix(last.n - overlap)?
+                        .concat(&first.prefix(overlap)?)?,
+                );
+            }
+            blocks.push(first.clone());
+        }
+        blocks.extend(other[1..].iter().cloned());
+        Ok(TriIntegersList(blocks))
+    }
+
+    /// Reverse complement of the sequence, A, C, G and T being placed on the
+    /// corners by `alphabet`
+    pub fn reverse_complement(&self, alphabet: &Alphabet) -> Result<TriIntegersList, IcgrError> {
+        self.iter()
+            .rev()
+            .map(|block| block.reverse_complement(alphabet))
+            .collect::<Result<Vec<_>, _>>()
+            .map(TriIntegersList)
+    }
+}
+
 fn merge_with_overlap(chunks: Vec<&[u8]>, overlap: usize) -> Result<Vec<u8>, IcgrError> {
     if chunks.is_empty() {
         return Ok(Vec::new());
@@ -222,7 +342,11 @@ impl TriIntegers {
         block_length: usize,
         overlap: u8,
         strict: bool,
+        alphabet: &Alphabet,
     ) -> Result<TriIntegersList, IcgrError> {
+        if !alphabet.is_square() {
+            return Err(IcgrError::UnsupportedAlphabet(alphabet.to_string()));
+        }
         let seq = String::from_utf8_lossy(sequence);
 
         let chunks: Vec<&str> = if seq.len() > block_length {
@@ -233,39 +357,35 @@ impl TriIntegers {
 
         let icgrs = chunks
             .into_par_iter()
-            .map(|chunk| Self::icgr_from_chunk(chunk, strict))
+            .map(|chunk| Self::icgr_from_chunk(chunk, strict, alphabet))
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(TriIntegersList(icgrs))
     }
 
-    fn icgr_from_chunk(chunk: &str, strict: bool) -> Result<TriIntegers, IcgrError> {
+    fn icgr_from_chunk(
+        chunk: &str,
+        strict: bool,
+        alphabet: &Alphabet,
+    ) -> Result<TriIntegers, IcgrError> {
         let base: i128 = 2;
         let mut xx = Vec::with_capacity(chunk.len());
         let mut yy = Vec::with_capacity(chunk.len());
 
         for (index, nucleotide) in chunk.chars().enumerate() {
             let new_index = index as u32;
-            let (aa, bb) = match index {
-                0 => match nucleotide {
-                    'A' => (1, 1),
-                    'T' => (-1, 1),
-                    'C' => (-1, -1),
-                    'G' => (1, -1),
-                    _ if strict => return Err(IcgrError::UnknownNucleotide(nucleotide)),
-                    _ => (0, 0),
-                },
-                _ => {
+            let corner = u8::try_from(nucleotide)
+                .ok()
+                .and_then(|symbol| alphabet.corner(symbol));
+            let (aa, bb) = match (index, corner) {
+                (0, Some(corner)) => corner,
+                (_, None) if strict => return Err(IcgrError::UnknownNucleotide(nucleotide)),
+                (0, None) => (0, 0),
+                (_, corner) => {
                     let (prev_x, prev_y) = (xx[index - 1], yy[index - 1]);
+                    let (f, g) = corner.unwrap_or((0, 0));
                     let power = base.pow(new_index);
-                    match nucleotide {
-                        'A' => (prev_x + power, prev_y + power),
-                        'T' => (prev_x - power, prev_y + power),
-                        'C' => (prev_x - power, prev_y - power),
-                        'G' => (prev_x + power, prev_y - power),
-                        _ if strict => return Err(IcgrError::UnknownNucleotide(nucleotide)),
-                        _ => (prev_x, prev_y),
-                    }
+                    (prev_x + power * f, prev_y + power * g)
                 }
             };
             xx.push(aa);
@@ -280,8 +400,266 @@ impl TriIntegers {
     }
 }
 
-/// Decodes ICGR values back into a nucleotide sequence.
-fn tri_integers_to_dna(tri_integers: TriIntegers) -> Vec<u8> {
+/// Longest block whose integers fit in an i128
+pub const MAX_BLOCK_LENGTH: usize = 127;
+
+/// Sequence operations computed on the integers ------------------------------
+///
+/// A block of n symbols on corners (f_i, g_i) of ±1 has x = Σ 2^i f_i, and
+/// likewise y with g_i. Mapping each f_i to the bit (f_i + 1) / 2 gives
+/// x = 2B - (2^n - 1) for an n-bit integer B, so that taking a prefix or a
+/// suffix, joining two blocks and reversing a block are bit operations on B.
+/// Complementing a nucleotide moves it to the mirror corner, which flips the
+/// sign of x, of y or of both.
+///
+/// The operations expect blocks encoded in strict mode: unknown symbols take
+/// no corner and are only caught when they leave the integers even.
+impl TriIntegers {
+    /// Bits of each coordinate, lowest bit for the first symbol
+    fn bits(&self) -> Result<(u128, u128), IcgrError> {
+        if self.n == 0 || self.n > MAX_BLOCK_LENGTH {
+            return Err(IcgrError::InvalidTriIntegers(format!(
+                "block length {}",
+                self.n
+            )));
+        }
+        let mask = (1u128 << self.n) - 1;
+        let to_bits = |value: &str| -> Result<u128, IcgrError> {
+            let value: i128 = value.parse()?;
+            // Digits of ±1 make an odd value of at most 2^n - 1
+            if value % 2 == 0 || value.unsigned_abs() > mask {
+                return Err(IcgrError::InvalidTriIntegers(format!(
+                    "{value} is not a block of {} symbols",
+                    self.n
+                )));
+            }
+            Ok((value as u128).wrapping_add(mask) >> 1)
+        };
+        Ok((to_bits(&self.x)?, to_bits(&self.y)?))
+    }
+
+    /// Block of `n` symbols with the given bits
+    fn from_bits(x: u128, y: u128, n: usize) -> Self {
+        let mask = (1u128 << n) - 1;
+        let value = |bits: u128| ((bits & mask) << 1).wrapping_sub(mask) as i128;
+        TriIntegers {
+            x: value(x).to_string(),
+            y: value(y).to_string(),
+            n,
+        }
+    }
+
+    /// The first `length` symbols of the block
+    pub fn prefix(&self, length: usize) -> Result<TriIntegers, IcgrError> {
+        let (x, y) = self.bits()?;
+        if length == 0 || length > self.n {
+            return Err(IcgrError::OutOfBounds {
+                position: length,
+                length: self.n,
+            });
+        }
+        Ok(TriIntegers::from_bits(x, y, length))
+    }
+
+    /// The symbols of the block from position `start` on
+    pub fn suffix(&self, start: usize) -> Result<TriIntegers, IcgrError> {
+        let (x, y) = self.bits()?;
+        if start >= self.n {
+            return Err(IcgrError::OutOfBounds {
+                position: start,
+                length: self.n,
+            });
+        }
+        Ok(TriIntegers::from_bits(
+            x >> start,
+            y >> start,
+            self.n - start,
+        ))
+    }
+
+    /// The block followed by the symbols of `other`
+    pub fn concat(&self, other: &TriIntegers) -> Result<TriIntegers, IcgrError> {
+        let ((x, y), (ox, oy)) = (self.bits()?, other.bits()?);
+        let n = self.n + other.n;
+        if n > MAX_BLOCK_LENGTH {
+            return Err(IcgrError::BlockTooLong(n));
+        }
+        Ok(TriIntegers::from_bits(
+            x | ox << self.n,
+            y | oy << self.n,
+            n,
+        ))
+    }
+
+    /// Corner of the symbol at `position`
+    pub fn corner(&self, position: usize) -> Result<(i128, i128), IcgrError> {
+        let (x, y) = self.bits()?;
+        if position >= self.n {
+            return Err(IcgrError::OutOfBounds {
+                position,
+                length: self.n,
+            });
+        }
+        let sign = |bits: u128| if bits >> position & 1 == 1 { 1 } else { -1 };
+        Ok((sign(x), sign(y)))
+    }
+
+    /// Positions at which two blocks of the same length have symbols on
+    /// different corners: a symbol differs when a bit of x or of y does.
+    pub fn mismatches(&self, other: &TriIntegers) -> Result<Vec<usize>, IcgrError> {
+        if self.n != other.n {
+            return Err(IcgrError::InvalidTriIntegers(format!(
+                "blocks of {} and {} symbols",
+                self.n, other.n
+            )));
+        }
+        if self == other {
+            return Ok(Vec::new());
+        }
+        let ((x, y), (ox, oy)) = (self.bits()?, other.bits()?);
+        let mut differing = (x ^ ox) | (y ^ oy);
+        let mut positions = Vec::with_capacity(differing.count_ones() as usize);
+        while differing != 0 {
+            positions.push(differing.trailing_zeros() as usize);
+            differing &= differing - 1;
+        }
+        Ok(positions)
+    }
+
+    /// Decodes the block, each corner giving the first symbol of its group
+    pub fn to_dna(&self, alphabet: &Alphabet) -> Vec<u8> {
+        tri_integers_to_dna(self.clone(), alphabet)
+    }
+
+    /// Reverse complement of the block, A, C, G and T being placed on the
+    /// corners by `alphabet`
+    pub fn reverse_complement(&self, alphabet: &Alphabet) -> Result<TriIntegers, IcgrError> {
+        let (sx, sy) = complement_signs(alphabet)?;
+        let (x, y) = self.bits()?;
+        // A negated coordinate has all its bits flipped
+        let mask = (1u128 << self.n) - 1;
+        let flip = |sign: i128| if sign < 0 { mask } else { 0 };
+        let reverse = |bits: u128| bits.reverse_bits() >> (128 - self.n);
+        Ok(TriIntegers::from_bits(
+            reverse(x) ^ flip(sx),
+            reverse(y) ^ flip(sy),
+            self.n,
+        ))
+    }
+}
+
+/// Signs applied to the coordinates of a corner to reach the corner of the
+/// complementary nucleotide.
+///
+/// When A, C, G and T have a corner each, the pair A, T and the pair C, G are
+/// either two parallel sides or the two diagonals of the square, so that the
+/// mirror of A onto T also sends C onto G.
+pub(crate) fn complement_signs(alphabet: &Alphabet) -> Result<(i128, i128), IcgrError> {
+    let corners = b"ATCG"
+        .iter()
+        .map(|&b| alphabet.corner(b))
+        .collect::<Option<Vec<_>>>()
+        .filter(|corners| corners.iter().all_unique())
+        .ok_or_else(|| IcgrError::NoComplement(alphabet.to_string()))?;
+    let (a, t) = (corners[0], corners[1]);
+    Ok((
+        if a.0 == t.0 { 1 } else { -1 },
+        if a.1 == t.1 { 1 } else { -1 },
+    ))
+}
+
+/// Rolling iCGR of the windows of a sequence ---------------------------------
+///
+/// Yields the start and the integers (x, y) of each window of `k` symbols,
+/// as `icgr_from_chunk` would compute them, in O(1) per window: the oldest
+/// symbol leaves with x' = (x - f_0) / 2 and the new one enters with
+/// 2^(k-1) f_k. Windows holding a symbol outside the alphabet are skipped.
+pub struct RollingIcgr<'a> {
+    sequence: &'a [u8],
+
+    /// Corner of each byte, lowercase symbols taking the corner of their
+    /// uppercase form when the alphabet does not list them
+    corners: [Option<(i128, i128)>; 256],
+
+    k: usize,
+
+    /// Position of the next symbol to read
+    next: usize,
+
+    /// Number of symbols in the current window
+    filled: usize,
+
+    x: i128,
+    y: i128,
+}
+
+impl<'a> RollingIcgr<'a> {
+    /// Rolls windows of `k` symbols, at most `MAX_BLOCK_LENGTH`, along a
+    /// sequence
+    pub fn new(sequence: &'a [u8], k: usize, alphabet: &Alphabet) -> Result<Self, IcgrError> {
+        if !alphabet.is_square() {
+            return Err(IcgrError::UnsupportedAlphabet(alphabet.to_string()));
+        }
+        if k == 0 {
+            return Err(IcgrError::InvalidTriIntegers(
+                "window of 0 symbols".to_string(),
+            ));
+        }
+        if k > MAX_BLOCK_LENGTH {
+            return Err(IcgrError::BlockTooLong(k));
+        }
+        let mut corners = [None; 256];
+        for (byte, corner) in corners.iter_mut().enumerate() {
+            let byte = byte as u8;
+            *corner = alphabet
+                .corner(byte)
+                .or_else(|| alphabet.corner(byte.to_ascii_uppercase()));
+        }
+        Ok(RollingIcgr {
+            sequence,
+            corners,
+            k,
+            next: 0,
+            filled: 0,
+            x: 0,
+            y: 0,
+        })
+    }
+}
+
+impl Iterator for RollingIcgr<'_> {
+    /// Start of the window and its integers
+    type Item = (usize, i128, i128);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next < self.sequence.len() {
+            let corner = self.corners[self.sequence[self.next] as usize];
+            self.next += 1;
+            let Some((f, g)) = corner else {
+                (self.filled, self.x, self.y) = (0, 0, 0);
+                continue;
+            };
+            if self.filled == self.k {
+                let oldest = self.sequence[self.next - 1 - self.k];
+                let (f0, g0) = self.corners[oldest as usize].unwrap_or_default();
+                self.x = (self.x - f0) / 2;
+                self.y = (self.y - g0) / 2;
+                self.filled -= 1;
+            }
+            self.x += f << self.filled;
+            self.y += g << self.filled;
+            self.filled += 1;
+            if self.filled == self.k {
+                return Some((self.next - self.k, self.x, self.y));
+            }
+        }
+        None
+    }
+}
+
+/// Decodes ICGR values back into a nucleotide sequence, each corner giving
+/// the first symbol of its group in the alphabet.
+fn tri_integers_to_dna(tri_integers: TriIntegers, alphabet: &Alphabet) -> Vec<u8> {
     let mut complete_dna = Vec::with_capacity(tri_integers.n);
     let base: i128 = 2;
 
@@ -294,13 +672,9 @@ fn tri_integers_to_dna(tri_integers: TriIntegers) -> Vec<u8> {
 
     for index in (0..tri_integers.n).rev() {
         // Get nucleotide
-        let nucleotide = match (an[index].signum(), bn[index].signum()) {
-            (1, 1) => 'A',
-            (1, -1) => 'G',
-            (-1, 1) => 'T',
-            (-1, -1) => 'C',
-            _ => 'N',
-        };
+        let nucleotide = alphabet
+            .symbol_at((an[index].signum(), bn[index].signum()))
+            .unwrap_or(b'N');
         seq.push(nucleotide);
 
         if index > 0 {
@@ -318,37 +692,10 @@ fn tri_integers_to_dna(tri_integers: TriIntegers) -> Vec<u8> {
     }
 
     seq.reverse();
-    complete_dna.extend(seq.into_iter().map(|c| c as u8));
+    complete_dna.extend(seq);
     complete_dna
 }
 
-/// Representation of a DNA sequence using the Integer Chaos Game Representation (ICGR) method.
-///
-/// This struct represents a DNA sequence using the Integer Chaos Game Representation (ICGR) method.
-/// It contains a vector of ICGR which represent the whole DNA sequence.
-///
-/// # Examples
-///
-/// ```
-/// use chaoscoder::icgr::IChaos;
-///
-/// let icgr = IChaos::new("seq1", "description", vec![1, 2, 3]);
-/// assert_eq!(icgr.id(), "seq1");
-/// assert_eq!(icgr.desc(), Some("description"));
-/// assert_eq!(icgr.icgrs(), &[1, 2, 3]);
-/// ```
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct Icgr {
-    /// A DNA sequence ID: all characters before first whitespace in sequence header
-    pub(crate) id: String,
-
-    /// A DNA sequence description: all characters after first whitespace
-    pub(crate) desc: Option<String>,
-
-    /// A vector of ICGR which represent the whole DNA sequence
-    pub(crate) tri_integers: TriIntegersList,
-}
-
 /// Function generating an iterator of chunks of sequence
 #[inline]
 fn str_chunks_overlap<'a>(
@@ -363,7 +710,8 @@ fn str_chunks_overlap<'a>(
     Box::new(
         (0..s.len())
             .step_by(chunk_size - overlap)
-            .take_while(move |&start| start < s.len())
+            // A last chunk shorter than the overlap could not be merged back
+            .take_while(move |&start| start == 0 || start + overlap <= s.len())
             .map(move |start| {
                 let end = usize::min(start + chunk_size, s.len());
                 &s[start..end]
@@ -377,11 +725,12 @@ pub trait ChaosEncoder {
         block_length: usize,
         overlap: u8,
         strict: bool,
+        alphabet: &Alphabet,
     ) -> Result<TriIntegersList, IcgrError>;
 }
 
 pub trait ChaosDecoder {
-    fn decode(&self, overlap: u8) -> Result<String, IcgrError>;
+    fn decode(&self, overlap: u8, alphabet: &Alphabet) -> Result<String, IcgrError>;
 }
 
 impl ChaosEncoder for [u8] {
@@ -390,14 +739,15 @@ impl ChaosEncoder for [u8] {
         block_length: usize,
         overlap: u8,
         strict: bool,
+        alphabet: &Alphabet,
     ) -> Result<TriIntegersList, IcgrError> {
-        TriIntegers::from_sequence(self, block_length, overlap, strict)
+        TriIntegers::from_sequence(self, block_length, overlap, strict, alphabet)
     }
 }
 
 impl ChaosDecoder for TriIntegersList {
-    fn decode(&self, overlap: u8) -> Result<String, IcgrError> {
-        self.to_dna(overlap)
+    fn decode(&self, overlap: u8, alphabet: &Alphabet) -> Result<String, IcgrError> {
+        self.to_dna(overlap, alphabet)
     }
 }
 
@@ -405,18 +755,45 @@ impl ChaosDecoder for TriIntegersList {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_rolling_icgr_matches_chunks() {
+        let alphabet = Alphabet::nucleotides();
+        let sequence = random_dna(300, 7);
+        let mut sequence = sequence.into_bytes();
+        sequence[100] = b'N';
+        sequence[150] = b'a';
+        for k in [1, 2, 31, 127] {
+            let windows: Vec<_> = RollingIcgr::new(&sequence, k, &alphabet).unwrap().collect();
+            let expected: Vec<_> = sequence
+                .windows(k)
+                .enumerate()
+                .filter(|(_, window)| !window.contains(&b'N'))
+                .map(|(start, window)| {
+                    let window = String::from_utf8(window.to_ascii_uppercase()).unwrap();
+                    let block = TriIntegers::icgr_from_chunk(&window, true, &alphabet).unwrap();
+                    (start, block.x.parse().unwrap(), block.y.parse().unwrap())
+                })
+                .collect();
+            assert_eq!(windows, expected);
+        }
+
+        assert!(RollingIcgr::new(&sequence, 0, &alphabet).is_err());
+        assert!(RollingIcgr::new(&sequence, 128, &alphabet).is_err());
+        assert_eq!(RollingIcgr::new(b"ACG", 4, &alphabet).unwrap().count(), 0);
+    }
+
     #[test]
     fn test_icgr_from_chunk_strict() {
-        let result = TriIntegers::icgr_from_chunk("ATGC", true);
+        let result = TriIntegers::icgr_from_chunk("ATGC", true, &Alphabet::nucleotides());
         assert!(result.is_ok());
 
-        let result = TriIntegers::icgr_from_chunk("ATGN", true);
+        let result = TriIntegers::icgr_from_chunk("ATGN", true, &Alphabet::nucleotides());
         assert!(result.is_err()); // 'N' not allowed in strict mode
     }
 
     #[test]
     fn test_icgr_from_chunk_non_strict() {
-        let result = TriIntegers::icgr_from_chunk("ATGN", false);
+        let result = TriIntegers::icgr_from_chunk("ATGN", false, &Alphabet::nucleotides());
         assert!(result.is_ok());
 
         let coords = result.unwrap();
@@ -430,6 +807,20 @@ mod tests {
         assert_eq!(chunks, vec!["ATGC", "GCGT", "GT"]);
     }
 
+    #[test]
+    fn test_str_chunks_overlap_short_tail() {
+        let seq = "ATGCGTA";
+        let chunks: Vec<&str> = str_chunks_overlap(seq, 4, 2).collect();
+        assert_eq!(chunks, vec!["ATGC", "GCGT", "GTA"]);
+        let chunks: Vec<&str> = str_chunks_overlap("ACGTACGTACGT", 5, 3).collect();
+        assert_eq!(chunks, vec!["ACGTA", "GTACG", "ACGTA", "GTACG", "ACGT"]);
+
+        let dna = Alphabet::nucleotides();
+        let sequence = random_dna(57, 42);
+        let encoded = sequence.as_bytes().encode(10, 3, true, &dna).unwrap();
+        assert_eq!(encoded.decode(3, &dna).unwrap(), sequence);
+    }
+
     #[test]
     fn test_merge_with_overlap() {
         let chunks = vec![b"ATGC".as_ref(), b"GCAT".as_ref()];
@@ -440,8 +831,9 @@ mod tests {
     #[test]
     fn test_encode_decode_roundtrip() {
         let dna = "ATGCGTACGTAGCTAGCTAG";
-        let encoded = dna.as_bytes().encode(6, 2, true).unwrap();
-        let decoded = encoded.decode(2).unwrap();
+        let dna_alphabet = Alphabet::nucleotides();
+        let encoded = dna.as_bytes().encode(6, 2, true, &dna_alphabet).unwrap();
+        let decoded = encoded.decode(2, &dna_alphabet).unwrap();
 
         // May not match exactly due to non-uniqueness of reverse CGR,
         // but should be the same length and base composition if strict mode was off
@@ -461,8 +853,188 @@ mod tests {
 
     #[test]
     fn test_tri_integers_to_dna() {
-        let encoded = TriIntegers::icgr_from_chunk("ATGC", true).unwrap();
-        let dna = tri_integers_to_dna(encoded.clone());
+        let encoded = TriIntegers::icgr_from_chunk("ATGC", true, &Alphabet::nucleotides()).unwrap();
+        let dna = tri_integers_to_dna(encoded.clone(), &Alphabet::nucleotides());
         assert_eq!(dna.len(), encoded.n);
     }
+
+    /// Pseudo-random nucleotides, reproducible from the seed
+    fn random_dna(length: usize, seed: u64) -> String {
+        let mut state = seed;
+        (0..length)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[(state >> 62) as usize] as char
+            })
+            .collect()
+    }
+
+    fn reverse_complement(sequence: &str) -> String {
+        String::from_utf8(crate::utils::reverse_complement(sequence.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_block_operations_match_sequence() {
+        for vertices in ["A,T,C,G", "T,A,G,C", "A,C,T,G"] {
+            let alphabet: Alphabet = vertices.parse().unwrap();
+            let encode = |s: &str| TriIntegers::icgr_from_chunk(s, true, &alphabet).unwrap();
+            for n in [1, 2, 7, 64, 100, MAX_BLOCK_LENGTH] {
+                let sequence = random_dna(n, n as u64);
+                let block = encode(&sequence);
+                assert_eq!(
+                    block.reverse_complement(&alphabet).unwrap(),
+                    encode(&reverse_complement(&sequence)),
+                    "{vertices} {sequence}"
+                );
+                for cut in 1..n {
+                    let (head, tail) = sequence.split_at(cut);
+                    assert_eq!(block.prefix(cut).unwrap(), encode(head));
+                    assert_eq!(block.suffix(cut).unwrap(), encode(tail));
+                    assert_eq!(encode(head).concat(&encode(tail)).unwrap(), block);
+                }
+                assert_eq!(block.prefix(n).unwrap(), block);
+                assert_eq!(block.suffix(0).unwrap(), block);
+            }
+        }
+    }
+
+    #[test]
+    fn test_block_mismatches() {
+        let dna = Alphabet::nucleotides();
+        let encode = |s: &str| TriIntegers::icgr_from_chunk(s, true, &dna).unwrap();
+        let reference = random_dna(100, 7);
+        let mut query = reference.clone().into_bytes();
+        for (position, symbol) in [(0, b'A'), (41, b'C'), (99, b'T')] {
+            query[position] = if query[position] == symbol {
+                b'G'
+            } else {
+                symbol
+            };
+        }
+        let query = String::from_utf8(query).unwrap();
+
+        let (a, b) = (encode(&reference), encode(&query));
+        assert_eq!(a.mismatches(&b).unwrap(), vec![0, 41, 99]);
+        assert!(a.mismatches(&a).unwrap().is_empty());
+        for position in [0, 41, 99] {
+            let corner = dna.corner(query.as_bytes()[position]).unwrap();
+            assert_eq!(b.corner(position).unwrap(), corner);
+        }
+        assert_eq!(b.to_dna(&dna), query.as_bytes());
+        assert!(a.mismatches(&a.prefix(10).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_block_operation_errors() {
+        let dna = Alphabet::nucleotides();
+        let block = TriIntegers::icgr_from_chunk("ACGT", true, &dna).unwrap();
+        assert!(matches!(
+            block.prefix(0),
+            Err(IcgrError::OutOfBounds { .. })
+        ));
+        assert!(matches!(
+            block.prefix(5),
+            Err(IcgrError::OutOfBounds { .. })
+        ));
+        assert!(matches!(
+            block.suffix(4),
+            Err(IcgrError::OutOfBounds { .. })
+        ));
+
+        let long = TriIntegers::icgr_from_chunk(&random_dna(100, 1), true, &dna).unwrap();
+        assert!(matches!(
+            long.concat(&long),
+            Err(IcgrError::BlockTooLong(200))
+        ));
+
+        let unknown = TriIntegers::icgr_from_chunk("NAGT", false, &dna).unwrap();
+        assert!(matches!(
+            unknown.prefix(2),
+            Err(IcgrError::InvalidTriIntegers(_))
+        ));
+
+        let shared: Alphabet = "AT,C,G,N".parse().unwrap();
+        assert!(matches!(
+            block.reverse_complement(&shared),
+            Err(IcgrError::NoComplement(_))
+        ));
+    }
+
+    #[test]
+    fn test_list_operations_match_sequence() {
+        let dna = Alphabet::nucleotides();
+        let sequence = random_dna(60, 42);
+        for (block_length, overlap) in [(10, 3), (4, 2), (100, 5)] {
+            let list = sequence
+                .as_bytes()
+                .encode(block_length, overlap, true, &dna)
+                .unwrap();
+            let decode = |list: TriIntegersList| list.decode(overlap, &dna).unwrap();
+            assert_eq!(list.sequence_len(overlap), sequence.len());
+
+            assert_eq!(
+                decode(list.reverse_complement(&dna).unwrap()),
+                reverse_complement(&sequence)
+            );
+            for cut in 0..=sequence.len() {
+                let (head, tail) = sequence.split_at(cut);
+                assert_eq!(decode(list.prefix(cut, overlap).unwrap()), head);
+                assert_eq!(decode(list.suffix(cut, overlap).unwrap()), tail);
+            }
+            assert!(list.prefix(61, overlap).is_err());
+            assert!(list.suffix(61, overlap).is_err());
+        }
+    }
+
+    #[test]
+    fn test_list_concat_matches_sequence() {
+        let dna = Alphabet::nucleotides();
+        let first = random_dna(150, 1);
+        let second = random_dna(130, 2);
+        for (block_length, overlap) in [(10, 3), (100, 5), (100, 20)] {
+            let encode = |s: &str| {
+                s.as_bytes()
+                    .encode(block_length, overlap, true, &dna)
+                    .unwrap()
+            };
+            for (a, b) in [
+                (&first[..], &second[..]),
+                (&first[..3], &second[..4]),
+                (&first[..], &second[..1]),
+                (&first[..101], &second[..102]),
+            ] {
+                let joined = encode(a).concat(&encode(b), overlap).unwrap();
+                assert_eq!(joined.decode(overlap, &dna).unwrap(), format!("{a}{b}"));
+            }
+            let empty = TriIntegersList::new(Vec::new());
+            assert_eq!(
+                encode(&first).concat(&empty, overlap).unwrap(),
+                encode(&first)
+            );
+        }
+    }
+
+    #[test]
+    fn test_alphabet_vertex_order() {
+        let swapped: Alphabet = "T,A,G,C".parse().unwrap();
+        let dna = TriIntegers::icgr_from_chunk("ATGC", true, &Alphabet::nucleotides()).unwrap();
+        let other = TriIntegers::icgr_from_chunk("ATGC", true, &swapped).unwrap();
+        // A and T swap corners: x changes sign, y is unchanged
+        assert_eq!(other.x, format!("{}", -dna.x.parse::<i128>().unwrap()));
+        assert_eq!(other.y, dna.y);
+        assert_eq!(tri_integers_to_dna(other, &swapped), b"ATGC");
+
+        // Groups decode to their first symbol
+        let grouped: Alphabet = "AN,T,C,G".parse().unwrap();
+        let encoded = b"ANTN".encode(10, 1, true, &grouped).unwrap();
+        assert_eq!(encoded.decode(1, &grouped).unwrap(), "AATA");
+
+        let hexagon: Alphabet = "A,T,C,G,U,N".parse().unwrap();
+        assert!(matches!(
+            b"ACGT".encode(10, 1, true, &hexagon),
+            Err(IcgrError::UnsupportedAlphabet(_))
+        ));
+    }
 }
@@ -0,0 +1,168 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+/// Vantage-point tree (VP-tree) for nearest-neighbour search in a metric space
+///
+/// The tree is stored implicitly: points are laid out in preorder, so the node
+/// covering the range `[lo, hi)` has its vantage point at `lo`, its inner subtree
+/// (points within `radius` of the vantage point) at `[lo + 1, mid)` and its
+/// outer subtree at `[mid, hi)`, with `mid = lo + 1 + (hi - lo) / 2`. Only the
+/// radius of each node needs to be kept along with the ordered points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VpTree {
+    /// Radius of the node whose vantage point is at the same position
+    radii: Vec<f64>,
+}
+
+/// Number of points in the inner subtree of a node covering `len` points
+#[inline]
+fn inner_len(len: usize) -> usize {
+    len / 2
+}
+
+impl VpTree {
+    /// Creates a tree from radii of points already laid out in tree order
+    pub fn from_radii(radii: Vec<f64>) -> Self {
+        VpTree { radii }
+    }
+
+    /// Radii of the nodes, in the order of the points
+    pub fn radii(&self) -> &[f64] {
+        &self.radii
+    }
+
+    /// Builds a tree over the points, returning them reordered in tree layout
+    pub fn build<T, F>(points: Vec<T>, dist: F) -> (Vec<T>, Self)
+    where
+        F: Fn(&T, &T) -> f64,
+    {
+        let mut ordered = Vec::with_capacity(points.len());
+        let mut radii = Vec::with_capacity(points.len());
+        Self::layout(points, &dist, &mut ordered, &mut radii);
+        (ordered, VpTree { radii })
+    }
+
+    fn layout<T, F>(points: Vec<T>, dist: &F, ordered: &mut Vec<T>, radii: &mut Vec<f64>)
+    where
+        F: Fn(&T, &T) -> f64,
+    {
+        let inner = inner_len(points.len());
+        let mut points = points.into_iter();
+        let Some(vantage) = points.next() else {
+            return;
+        };
+
+        let mut rest: Vec<(f64, T)> = points.map(|p| (dist(&vantage, &p), p)).collect();
+        rest.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let radius = if inner > 0 { rest[inner - 1].0 } else { 0.0 };
+
+        ordered.push(vantage);
+        radii.push(radius);
+
+        let outer: Vec<T> = rest.split_off(inner).into_iter().map(|(_, p)| p).collect();
+        let inner: Vec<T> = rest.into_iter().map(|(_, p)| p).collect();
+        Self::layout(inner, dist, ordered, radii);
+        Self::layout(outer, dist, ordered, radii);
+    }
+
+    /// Finds the `k` points closest to the query, sorted by increasing distance.
+    ///
+    /// `dist` returns the distance between the query and the point at an index.
+    pub fn search<F>(&self, k: usize, dist: F) -> Vec<(usize, f64)>
+    where
+        F: Fn(usize) -> f64,
+    {
+        let mut best = Vec::with_capacity(k + 1);
+        if k > 0 {
+            self.search_node(0, self.radii.len(), k, &dist, &mut best);
+        }
+        best
+    }
+
+    fn search_node<F>(&self, lo: usize, hi: usize, k: usize, dist: &F, best: &mut Vec<(usize, f64)>)
+    where
+        F: Fn(usize) -> f64,
+    {
+        if lo >= hi {
+            return;
+        }
+
+        let d = dist(lo);
+        if best.len() < k || d < best[best.len() - 1].1 {
+            let at = best.partition_point(|&(_, bd)| bd <= d);
+            best.insert(at, (lo, d));
+            best.truncate(k);
+        }
+
+        let mid = lo + 1 + inner_len(hi - lo);
+        let radius = self.radii[lo];
+        // Distance to the k-th closest point found so far
+        let tau = |best: &Vec<(usize, f64)>| {
+            if best.len() < k {
+                f64::INFINITY
+            } else {
+                best[best.len() - 1].1
+            }
+        };
+
+        if d <= radius {
+            self.search_node(lo + 1, mid, k, dist, best);
+            if d + tau(best) >= radius {
+                self.search_node(mid, hi, k, dist, best);
+            }
+        } else {
+            self.search_node(mid, hi, k, dist, best);
+            if d - tau(best) <= radius {
+                self.search_node(lo + 1, mid, k, dist, best);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brute_force(points: &[f64], query: f64, k: usize) -> Vec<f64> {
+        let mut dists: Vec<f64> = points.iter().map(|p| (p - query).abs()).collect();
+        dists.sort_by(|a, b| a.total_cmp(b));
+        dists.truncate(k);
+        dists
+    }
+
+    #[test]
+    fn test_vptree_matches_brute_force() {
+        let points: Vec<f64> = (0..50).map(|i| ((i * 37) % 101) as f64 / 7.0).collect();
+        let (ordered, tree) = VpTree::build(points.clone(), |a, b| (a - b).abs());
+        assert_eq!(ordered.len(), points.len());
+
+        for query in [-3.0, 0.0, 2.5, 7.1, 20.0] {
+            for k in [1, 3, 10, 60] {
+                let found: Vec<f64> = tree
+                    .search(k, |i| (ordered[i] - query).abs())
+                    .into_iter()
+                    .map(|(_, d)| d)
+                    .collect();
+                assert_eq!(found, brute_force(&points, query, k));
+            }
+        }
+    }
+
+    #[test]
+    fn test_vptree_roundtrip_from_radii() {
+        let points = vec![1.0, 5.0, 2.0, 8.0, 3.0];
+        let (ordered, tree) = VpTree::build(points, |a: &f64, b: &f64| (a - b).abs());
+        let restored = VpTree::from_radii(tree.radii().to_vec());
+        let nearest = restored.search(1, |i| (ordered[i] - 7.5_f64).abs());
+        assert_eq!(ordered[nearest[0].0], 8.0);
+    }
+
+    #[test]
+    fn test_vptree_empty() {
+        let (ordered, tree) = VpTree::build(Vec::<f64>::new(), |a, b| (a - b).abs());
+        assert!(ordered.is_empty());
+        assert!(tree.search(3, |_| 0.0).is_empty());
+    }
+}
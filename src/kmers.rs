@@ -0,0 +1,369 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::alphabet::Alphabet;
+use crate::icgr::{self, RollingIcgr};
+
+/// Longest k-mer whose iCGR integers fit in an i64
+pub const MAX_K: usize = 63;
+
+/// Signature of the binary k-mer table
+const MAGIC: &[u8; 4] = b"CKMR";
+
+/// k-mer identified by its iCGR integers ------------------------------------
+///
+/// A k-mer on corners (f_i, g_i) has x = Σ 2^i f_i and y = Σ 2^i g_i. For a
+/// fixed k the integers are a bijection with the k-mers over the four corners
+/// of the alphabet, so they are an exact key. As in the iCGR blocks, the bit
+/// (f_i + 1) / 2 of x is bit i of the integer B with x = 2B - (2^k - 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Kmer {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl Kmer {
+    fn mask(k: usize) -> u64 {
+        u64::MAX >> (64 - k)
+    }
+
+    /// k-mer with the given bits
+    fn from_bits(x: u64, y: u64, k: usize) -> Self {
+        let mask = Kmer::mask(k);
+        let value = |bits: u64| ((bits & mask) << 1).wrapping_sub(mask) as i64;
+        Kmer {
+            x: value(x),
+            y: value(y),
+        }
+    }
+
+    fn bits(self, k: usize) -> (u64, u64) {
+        let mask = Kmer::mask(k);
+        let bits = |value: i64| (value as u64).wrapping_add(mask) >> 1;
+        (bits(self.x), bits(self.y))
+    }
+
+    /// Reverse complement of the k-mer, `complement` being the signs given by
+    /// `complement_signs` for the alphabet: the bits are reversed, and those
+    /// of a coordinate negated by the complement are flipped.
+    pub fn reverse_complement(self, k: usize, complement: (i128, i128)) -> Kmer {
+        let (x, y) = self.bits(k);
+        let reverse = |bits: u64| bits.reverse_bits() >> (64 - k);
+        let flip = |sign: i128| if sign < 0 { Kmer::mask(k) } else { 0 };
+        Kmer::from_bits(
+            reverse(x) ^ flip(complement.0),
+            reverse(y) ^ flip(complement.1),
+            k,
+        )
+    }
+
+    /// Smallest of the k-mer and of its reverse complement
+    pub fn canonical(self, k: usize, complement: (i128, i128)) -> Kmer {
+        self.min(self.reverse_complement(k, complement))
+    }
+
+    /// Symbols of the k-mer, each corner giving the first symbol of its group
+    pub fn to_dna(self, k: usize, alphabet: &Alphabet) -> String {
+        let (x, y) = self.bits(k);
+        let sign = |bits: u64, i: usize| if bits >> i & 1 == 1 { 1 } else { -1 };
+        (0..k)
+            .map(|i| {
+                alphabet
+                    .symbol_at((sign(x, i), sign(y, i)))
+                    .expect("square alphabets have a symbol on each corner") as char
+            })
+            .collect()
+    }
+}
+
+/// Counts of the k-mers of sequences, keyed by their iCGR integers
+#[derive(Debug, Clone, PartialEq)]
+pub struct KmerCounts {
+    k: usize,
+
+    /// Whether a k-mer and its reverse complement are counted together
+    canonical: bool,
+
+    counts: HashMap<Kmer, u64>,
+
+    alphabet: Alphabet,
+
+    /// Signs sending a corner to the corner of its complement
+    complement: (i128, i128),
+}
+
+impl KmerCounts {
+    /// Creates empty counts of k-mers of length k
+    pub fn new(k: usize, canonical: bool) -> anyhow::Result<Self> {
+        if k == 0 || k > MAX_K {
+            anyhow::bail!("k-mers are 1 to {MAX_K} bases long, got {k}");
+        }
+        let alphabet = Alphabet::nucleic_acids();
+        Ok(KmerCounts {
+            k,
+            canonical,
+            counts: HashMap::new(),
+            complement: icgr::complement_signs(&alphabet)?,
+            alphabet,
+        })
+    }
+
+    /// Adds the k-mers of a sequence, rolling the integers along it.
+    ///
+    /// U counts as T, and k-mers containing a symbol other than A, C, G, T or
+    /// U are skipped.
+    pub fn add_sequence(&mut self, sequence: &[u8]) {
+        let windows =
+            RollingIcgr::new(sequence, self.k, &self.alphabet).expect("k is checked on creation");
+        for (_, x, y) in windows {
+            // |x| and |y| are below 2^k
+            let kmer = Kmer {
+                x: x as i64,
+                y: y as i64,
+            };
+            let kmer = if self.canonical {
+                kmer.canonical(self.k, self.complement)
+            } else {
+                kmer
+            };
+            *self.counts.entry(kmer).or_insert(0) += 1;
+        }
+    }
+
+    /// Adds the counts of another table of same k and strandedness
+    pub fn merge(&mut self, other: KmerCounts) -> anyhow::Result<()> {
+        if (self.k, self.canonical) != (other.k, other.canonical) {
+            anyhow::bail!("Cannot merge tables of different k-mers");
+        }
+        for (kmer, count) in other.counts {
+            *self.counts.entry(kmer).or_insert(0) += count;
+        }
+        Ok(())
+    }
+
+    /// k-mers counted at least `min_count` times, sorted by their integers
+    pub fn sorted(&self, min_count: u64) -> Vec<(Kmer, u64)> {
+        let mut table: Vec<(Kmer, u64)> = self
+            .counts
+            .iter()
+            .filter(|&(_, &count)| count >= min_count)
+            .map(|(&kmer, &count)| (kmer, count))
+            .collect();
+        table.sort_unstable();
+        table
+    }
+
+    /// Writes the k-mers counted at least `min_count` times as TSV
+    pub fn write_tsv<W: Write>(&self, mut writer: W, min_count: u64) -> io::Result<()> {
+        writeln!(writer, "#kmer\tx\ty\tcount")?;
+        for (kmer, count) in self.sorted(min_count) {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{count}",
+                kmer.to_dna(self.k, &self.alphabet),
+                kmer.x,
+                kmer.y
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the k-mers counted at least `min_count` times as a binary table:
+    /// the signature `CKMR`, k and the canonical flag as bytes, the number of
+    /// k-mers, then x, y and the count of each k-mer, all little-endian 64-bit
+    /// integers.
+    pub fn write_binary<W: Write>(&self, mut writer: W, min_count: u64) -> io::Result<()> {
+        let table = self.sorted(min_count);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[self.k as u8, u8::from(self.canonical)])?;
+        writer.write_all(&(table.len() as u64).to_le_bytes())?;
+        for (kmer, count) in table {
+            writer.write_all(&kmer.x.to_le_bytes())?;
+            writer.write_all(&kmer.y.to_le_bytes())?;
+            writer.write_all(&count.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+
+    /// Count of a k-mer, or of its canonical form in canonical tables
+    fn count_of(table: &KmerCounts, kmer: &Kmer) -> u64 {
+        let kmer = if table.canonical {
+            kmer.canonical(table.k, table.complement)
+        } else {
+            *kmer
+        };
+        table.counts.get(&kmer).copied().unwrap_or(0)
+    }
+
+    /// Reads a binary table written by `write_binary`
+    fn read_binary<R: io::Read>(mut reader: R) -> anyhow::Result<KmerCounts> {
+        let mut header = [0u8; 14];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            anyhow::bail!("Not a chaoscoder k-mer table");
+        }
+        let mut table = KmerCounts::new(header[4] as usize, header[5] == 1)?;
+        let entries = u64::from_le_bytes(header[6..].try_into()?);
+
+        let mut entry = [0u8; 24];
+        for _ in 0..entries {
+            reader.read_exact(&mut entry)?;
+            let kmer = Kmer {
+                x: i64::from_le_bytes(entry[..8].try_into()?),
+                y: i64::from_le_bytes(entry[8..16].try_into()?),
+            };
+            table
+                .counts
+                .insert(kmer, u64::from_le_bytes(entry[16..].try_into()?));
+        }
+        Ok(table)
+    }
+
+    fn naive_counts(sequence: &[u8], k: usize) -> HashMap<String, u64> {
+        let mut counts = HashMap::new();
+        for kmer in sequence.windows(k) {
+            if kmer.iter().all(|b| b"ACGT".contains(b)) {
+                *counts
+                    .entry(String::from_utf8(kmer.to_vec()).unwrap())
+                    .or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn test_kmer_matches_icgr() {
+        let sequence = b"GATTACAGGCATGCATTAGCCGATTACAGTGACCATGCATGCCTAGCATGCAGTACGTACGA";
+        let k = sequence.len();
+        let mut counts = KmerCounts::new(k, false).unwrap();
+        counts.add_sequence(sequence);
+        let (kmer, count) = counts.sorted(1)[0];
+        assert_eq!(count, 1);
+        let alphabet = Alphabet::nucleotides();
+        assert_eq!(kmer.to_dna(k, &alphabet).as_bytes(), sequence);
+
+        let (mut x, mut y) = (0i128, 0i128);
+        for (i, &nucleotide) in sequence.iter().enumerate() {
+            let (f, g) = alphabet.corner(nucleotide).unwrap();
+            x += f << i;
+            y += g << i;
+        }
+        assert_eq!((i128::from(kmer.x), i128::from(kmer.y)), (x, y));
+    }
+
+    #[test]
+    fn test_kmer_counts_match_naive() {
+        let sequence = b"ACGTTGCAAGNCTTACGGATCCAGTACGATCGATTACAGGCATGCAacgtU";
+        let normalised: Vec<u8> = sequence
+            .to_ascii_uppercase()
+            .into_iter()
+            .map(|b| if b == b'U' { b'T' } else { b })
+            .collect();
+        for k in [1, 3, 5] {
+            let mut counts = KmerCounts::new(k, false).unwrap();
+            counts.add_sequence(sequence);
+            let found: HashMap<String, u64> = counts
+                .sorted(1)
+                .into_iter()
+                .map(|(kmer, count)| (kmer.to_dna(k, &counts.alphabet), count))
+                .collect();
+            assert_eq!(found, naive_counts(&normalised, k));
+        }
+    }
+
+    #[test]
+    fn test_kmer_reverse_complement() {
+        let kmer = b"AACGTGCCTA";
+        let k = kmer.len();
+        let mut counts = KmerCounts::new(k, false).unwrap();
+        counts.add_sequence(kmer);
+        let (key, _) = counts.sorted(1)[0];
+        let rc = key.reverse_complement(k, counts.complement);
+        assert_eq!(
+            rc.to_dna(k, &counts.alphabet).as_bytes(),
+            utils::reverse_complement(kmer)
+        );
+        assert_eq!(rc.reverse_complement(k, counts.complement), key);
+
+        // Complements on parallel sides or on the diagonals of the square
+        for groups in ["T,A,G,C", "A,C,T,G"] {
+            let alphabet: Alphabet = groups.parse().unwrap();
+            let (_, x, y) = RollingIcgr::new(kmer, k, &alphabet)
+                .unwrap()
+                .next()
+                .unwrap();
+            let key = Kmer {
+                x: x as i64,
+                y: y as i64,
+            };
+            let complement = icgr::complement_signs(&alphabet).unwrap();
+            assert_eq!(
+                key.reverse_complement(k, complement)
+                    .to_dna(k, &alphabet)
+                    .as_bytes(),
+                utils::reverse_complement(kmer),
+                "{groups}"
+            );
+        }
+
+        // 63-mers use the whole range of i64
+        let long = [b'A'; MAX_K];
+        let mut counts = KmerCounts::new(MAX_K, false).unwrap();
+        counts.add_sequence(&long);
+        let (key, _) = counts.sorted(1)[0];
+        assert_eq!((key.x, key.y), (i64::MAX, i64::MAX));
+        assert_eq!(
+            key.reverse_complement(MAX_K, counts.complement)
+                .to_dna(MAX_K, &counts.alphabet),
+            "T".repeat(MAX_K)
+        );
+    }
+
+    #[test]
+    fn test_canonical_counts_both_strands() {
+        let sequence = b"ACGTTGCAAGCTTACGGATCCAGTACGATCGATTACAGGCATGCA";
+        let mut forward = KmerCounts::new(7, true).unwrap();
+        forward.add_sequence(sequence);
+        let mut reverse = KmerCounts::new(7, true).unwrap();
+        reverse.add_sequence(&utils::reverse_complement(sequence));
+        assert_eq!(forward, reverse);
+
+        let mut stranded = KmerCounts::new(7, false).unwrap();
+        stranded.add_sequence(sequence);
+        stranded.add_sequence(&utils::reverse_complement(sequence));
+        // Odd k-mers are never their own reverse complement
+        for (kmer, count) in stranded.sorted(1) {
+            assert_eq!(count_of(&forward, &kmer), count);
+        }
+    }
+
+    #[test]
+    fn test_kmer_tables_output() {
+        let mut counts = KmerCounts::new(2, false).unwrap();
+        counts.add_sequence(b"AAAT");
+        let mut tsv = Vec::new();
+        counts.write_tsv(&mut tsv, 2).unwrap();
+        assert_eq!(
+            String::from_utf8(tsv).unwrap(),
+            "#kmer\tx\ty\tcount\nAA\t3\t3\t2\n"
+        );
+
+        let mut binary = Vec::new();
+        counts.write_binary(&mut binary, 1).unwrap();
+        assert_eq!(binary.len(), 14 + 2 * 24);
+        assert_eq!(read_binary(binary.as_slice()).unwrap(), counts);
+        assert!(read_binary(&b"CKMX"[..]).is_err());
+        assert!(KmerCounts::new(64, false).is_err());
+    }
+}
@@ -3,21 +3,39 @@
 // This file may not be copied, modified, or distributed except according
 // to those terms.
 
-use crate::cli::{Cli, Commands};
-use crate::icgr::{ChaosDecoder, ChaosEncoder};
-use anyhow::Context;
-use clap::Parser;
+use crate::cli::{Cli, Commands, DbCommands, KmerFormat};
+use crate::icgr::ChaosEncoder;
+use clap::{Parser, ValueEnum};
 use itertools::Itertools;
 use noodles::fasta;
+use rayon::prelude::*;
 use std::fs::{File, OpenOptions};
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
 
+mod alphabet;
+mod annotation;
 mod bicgr;
 mod cgr;
+mod classify;
 mod cli;
+mod cluster;
+mod db;
+mod diff;
+mod difference;
+mod dotplot;
 mod error;
+mod fcgr;
+mod grep;
 mod icgr;
+mod index;
+mod kmers;
+mod pdf;
+mod protein;
+mod raster;
+mod render;
+mod scan;
+mod screen;
 mod utils;
 
 fn main() -> anyhow::Result<()> {
@@ -53,6 +71,17 @@ fn main() -> anyhow::Result<()> {
             let overlap: u8 = args.overlap;
             let strict: bool = args.strict;
 
+            let header = bicgr::Header {
+                alphabet: args.vertices,
+                molecule: if args.rna {
+                    bicgr::Molecule::Rna
+                } else {
+                    bicgr::Molecule::Dna
+                },
+            };
+            let alphabet = header.encoding_alphabet().map_err(anyhow::Error::msg)?;
+            header.write_all(&mut destination)?;
+
             for result in fasta_reader.records() {
                 let record = result?;
                 let seq = record.sequence();
@@ -62,7 +91,9 @@ fn main() -> anyhow::Result<()> {
                     continue;
                 }
 
-                let encoded = seq.as_ref().encode(block_length, overlap, strict)?;
+                let encoded = seq
+                    .as_ref()
+                    .encode(block_length, overlap, strict, &alphabet)?;
                 let bicgr = bicgr::Record {
                     seq_id: record.definition().name().to_string(),
                     desc: record
@@ -78,7 +109,7 @@ fn main() -> anyhow::Result<()> {
         Commands::Decode(args) => {
             let from_stdin = args.file.as_ref().is_none_or(|p| p == Path::new("-"));
 
-            let reader: Box<dyn BufRead> = if from_stdin {
+            let mut reader: Box<dyn BufRead> = if from_stdin {
                 let stdin = io::stdin();
                 let stdin_lock = stdin.lock();
                 Box::new(stdin_lock)
@@ -93,12 +124,13 @@ fn main() -> anyhow::Result<()> {
                 Box::new(io::stdout().lock())
             };
 
+            let header = bicgr::read_header(&mut reader)?;
             let records = bicgr::read_from(reader)
                 .map_err(|e| format!("Failed to read records: {e}"))
                 .unwrap();
 
             for record in records {
-                let seq = record.tri_integers.decode(record.overlap)?;
+                let seq = header.decode(&record)?;
 
                 writeln!(
                     destination,
@@ -109,68 +141,392 @@ fn main() -> anyhow::Result<()> {
                 writeln!(destination, "{seq}")?;
             }
         }
+        Commands::Diff(args) => {
+            let read = |path: &Path| -> anyhow::Result<(bicgr::Header, Vec<bicgr::Record>)> {
+                let mut reader = BufReader::new(File::open(path)?);
+                let header = bicgr::read_header(&mut reader)?;
+                Ok((header, bicgr::read_from(reader)?))
+            };
+            let (reference_header, references) = read(&args.reference)?;
+            let (query_header, queries) = read(&args.query)?;
+            for (path, records) in [(&args.reference, &references), (&args.query, &queries)] {
+                if let Some(seq_id) = diff::unknown_symbols(records) {
+                    eprintln!(
+                        "Warning: {} was not encoded with --strict ({seq_id} has unknown symbols), \
+                         substitutions around unknown symbols may be wrong",
+                        path.display()
+                    );
+                }
+            }
+
+            let diff = diff::Diff::new(&reference_header, &references, &query_header, &queries);
+            for (seq_id, reason) in &diff.skipped {
+                eprintln!("Skipping {seq_id}: {reason}");
+            }
+
+            let destination: Box<dyn Write> = if let Some(out) = args.output {
+                Box::new(File::create(out)?)
+            } else {
+                Box::new(io::stdout().lock())
+            };
+            diff.write_all(
+                BufWriter::new(destination),
+                &args.reference.to_string_lossy(),
+            )?;
+        }
         Commands::Draw(args) => {
             let source = File::open(args.file)?;
-            cgr::draw(source, args.output)?
+            cgr::draw(
+                source,
+                args.output,
+                args.records,
+                &args.alphabet,
+                args.canonical,
+                &args.options,
+            )?
         }
         Commands::Compare(args) => {
-            let mut qfiles = Vec::new();
-            let mut rfiles = Vec::new();
+            let qfiles =
+                utils::sequence_files(args.query.as_ref(), args.queries.as_ref(), "--queries")?;
 
-            match (&args.query, &args.reference) {
-                (Some(q), Some(r)) => {
-                    qfiles.push(q.to_string_lossy().into_owned());
-                    rfiles.push(r.to_string_lossy().into_owned());
+            let ssim = if let Some(db_file) = &args.db {
+                let database = db::Database::read_from(BufReader::new(File::open(db_file)?))?;
+                if args.canonical && !database.canonical {
+                    anyhow::bail!(
+                        "Database {} was not built with --canonical",
+                        db_file.display()
+                    );
                 }
-                (Some(q), None) => {
-                    qfiles.push(q.to_string_lossy().into_owned());
+                database.compare(&qfiles, args.records)?
+            } else {
+                let rfiles =
+                    utils::sequence_files(args.reference.as_ref(), args.refs.as_ref(), "--refs")?;
 
-                    let ref_file = args.refs.as_ref().context("Missing --refs file")?;
-                    rfiles = utils::read_lines(ref_file)?.map_while(Result::ok).collect();
-                }
-                (None, Some(r)) => {
-                    let query_file = args.queries.as_ref().context("Missing --queries file")?;
-                    qfiles = utils::read_lines(query_file)?
-                        .map_while(Result::ok)
-                        .collect();
+                // Draw each genome once, then score the pairs on the configured thread pool
+                let (genomes, pairs) = utils::comparison_pairs(qfiles, rfiles, args.allvsall);
 
-                    rfiles.push(r.to_string_lossy().into_owned());
-                }
-                (None, None) => {
-                    let query_file = args.queries.as_ref().context("Missing --queries file")?;
-                    let ref_file = args.refs.as_ref().context("Missing --refs file")?;
+                let images = genomes
+                    .par_iter()
+                    .map(|genome| {
+                        cgr::genome_images(genome, args.records, &args.alphabet, args.canonical)
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
 
-                    qfiles = utils::read_lines(query_file)?
-                        .map_while(Result::ok)
-                        .collect();
-                    rfiles = utils::read_lines(ref_file)?.map_while(Result::ok).collect();
+                // Expand genome pairs into pairs of images, one per record in per-record mode
+                let mut image_pairs: Vec<_> = pairs
+                    .iter()
+                    .flat_map(|&(q, r)| images[q].iter().cartesian_product(&images[r]))
+                    .collect();
+                if args.allvsall {
+                    for genome in &images {
+                        image_pairs.extend(genome.iter().tuple_combinations::<(_, _)>());
+                    }
                 }
-            }
 
-            let mut ssim = Vec::new();
+                image_pairs
+                    .par_iter()
+                    .map(|&(q, r)| cgr::compare_images(q, r))
+                    .collect::<anyhow::Result<Vec<_>>>()?
+            };
 
-            if args.allvsall {
-                qfiles.extend(rfiles.clone());
-                for pair in qfiles.into_iter().combinations_with_replacement(2) {
-                    ssim.push(cgr::compare_genomes(&pair[0], &pair[1])?);
+            if let Some(output) = args.output {
+                let mut out = OpenOptions::new().append(true).create(true).open(output)?;
+                for result in ssim {
+                    writeln!(out, "{result}")?;
                 }
             } else {
-                for (q, r) in qfiles.iter().cartesian_product(&rfiles) {
-                    ssim.push(cgr::compare_genomes(q, r)?);
+                for result in ssim {
+                    println!("{result}");
                 }
             }
+        }
+        Commands::Search(args) => {
+            let qfiles =
+                utils::sequence_files(args.query.as_ref(), args.queries.as_ref(), "--queries")?;
+
+            let database = db::Database::read_from(BufReader::new(File::open(&args.db)?))?;
+            let hits = database.search(&qfiles, args.top_k)?;
 
             if let Some(output) = args.output {
                 let mut out = OpenOptions::new().append(true).create(true).open(output)?;
-                for result in ssim {
-                    writeln!(out, "{result}")?;
+                for hit in hits {
+                    writeln!(out, "{hit}")?;
                 }
             } else {
-                for result in ssim {
-                    println!("{result}");
+                for hit in hits {
+                    println!("{hit}");
+                }
+            }
+        }
+        Commands::Classify(args) => {
+            let labels = classify::read_labels(&args.labels)?;
+
+            let database = if let Some(db_file) = &args.db {
+                db::Database::read_from(BufReader::new(File::open(db_file)?))?
+            } else {
+                let references: Vec<String> = labels.iter().map(|(f, _)| f.clone()).collect();
+                db::Database::build(&references, args.k, false)?
+            };
+            let classifier = classify::Classifier::new(
+                database,
+                &labels,
+                args.neighbours as usize,
+                args.weighted,
+            )?;
+
+            if let Some(cv) = &args.cv {
+                classifier
+                    .cross_validate()
+                    .write_all(BufWriter::new(File::create(cv)?))?;
+            }
+
+            // Queries may be omitted when only the cross-validation report is wanted
+            if args.cv.is_none() || args.query.is_some() || args.queries.is_some() {
+                let qfiles =
+                    utils::sequence_files(args.query.as_ref(), args.queries.as_ref(), "--queries")?;
+                let predictions = classifier.classify(&qfiles, args.records)?;
+
+                if let Some(output) = args.output {
+                    let mut out = OpenOptions::new().append(true).create(true).open(output)?;
+                    for prediction in predictions {
+                        writeln!(out, "{prediction}")?;
+                    }
+                } else {
+                    for prediction in predictions {
+                        println!("{prediction}");
+                    }
+                }
+            }
+        }
+        Commands::DiffImage(args) => {
+            let signature = |path: &Path| -> anyhow::Result<fcgr::Fcgr> {
+                let records = utils::read_records(path)?;
+                if records.is_empty() {
+                    anyhow::bail!("No sequence found in {}", path.display());
+                }
+                let mut fcgr = fcgr::Fcgr::new(args.k);
+                for record in &records {
+                    fcgr.add_sequence(record.sequence().as_ref());
+                }
+                Ok(fcgr)
+            };
+            let diff = difference::FcgrDifference::new(
+                signature(&args.query)?,
+                signature(&args.reference)?,
+            )?;
+
+            diff.write_image(&args.output, args.size, args.colour_map)?;
+            if let Some(map) = &args.dssim_map {
+                let dssim = diff.write_dssim_map(map, args.size)?;
+                eprintln!("DSSIM between the FCGR images: {dssim}");
+            }
+
+            match &args.kmers {
+                Some(path) => diff.write_top(BufWriter::new(File::create(path)?), args.top)?,
+                None => diff.write_top(io::stdout().lock(), args.top)?,
+            }
+        }
+        Commands::Dotplot(args) => {
+            let windows = |path: &Path| -> anyhow::Result<Vec<dotplot::Window>> {
+                let records = utils::read_records(path)?;
+                if records.is_empty() {
+                    anyhow::bail!("No sequence found in {}", path.display());
                 }
+                Ok(dotplot::windows(
+                    &records,
+                    args.k,
+                    args.window as usize,
+                    args.step as usize,
+                ))
+            };
+            let dotplot = dotplot::Dotplot::new(windows(&args.query)?, windows(&args.reference)?);
+            dotplot.write_image(&args.output, args.size, args.colour_map)?;
+            if let Some(path) = &args.tsv {
+                dotplot.write_all(BufWriter::new(File::create(path)?))?;
+            }
+        }
+        Commands::Grep(args) => {
+            let mut reader = BufReader::new(File::open(&args.file)?);
+            let header = bicgr::read_header(&mut reader)?;
+            let records = bicgr::read_from(reader)?;
+            let matches = grep::find_all(&header, &records, &args.pattern, args.revcomp);
+
+            let mut output: Box<dyn Write> = match &args.output {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(BufWriter::new(io::stdout().lock())),
+            };
+            for occurrence in &matches {
+                writeln!(output, "{occurrence}")?;
+            }
+        }
+        Commands::Kmers(args) => {
+            let records = utils::read_records(&args.file)?;
+            let empty = kmers::KmerCounts::new(args.k as usize, args.canonical)?;
+            let counts = records
+                .par_iter()
+                .map(|record| {
+                    let mut counts = empty.clone();
+                    counts.add_sequence(record.sequence().as_ref());
+                    Ok::<_, anyhow::Error>(counts)
+                })
+                .try_reduce(
+                    || empty.clone(),
+                    |mut counts, other| {
+                        counts.merge(other)?;
+                        Ok(counts)
+                    },
+                )?;
+
+            let output: Box<dyn Write> = match &args.output {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(BufWriter::new(io::stdout().lock())),
+            };
+            match args.format {
+                KmerFormat::Tsv => counts.write_tsv(output, args.min_count)?,
+                KmerFormat::Binary => counts.write_binary(output, args.min_count)?,
+            }
+        }
+        Commands::Scan(args) => {
+            let records = utils::read_records(&args.file)?;
+            let windows = scan::scan(
+                &records,
+                args.k,
+                args.window as usize,
+                args.step as usize,
+                args.canonical,
+            );
+
+            let mut output: Box<dyn Write> = match &args.output {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(io::stdout().lock()),
+            };
+            writeln!(
+                output,
+                "track type=bedGraph name=\"FCGR distance\" description=\"Distance of {}-mer window signatures to the genome signature\"",
+                args.k
+            )?;
+            for bin in scan::bedgraph(&windows, args.step as usize) {
+                writeln!(output, "{bin}")?;
+            }
+
+            if let Some(path) = &args.outliers {
+                let distances: Vec<f64> = windows.iter().map(|w| w.distance).collect();
+                let mut bed = BufWriter::new(File::create(path)?);
+                for (window, z) in windows.iter().zip(scan::robust_z_scores(&distances)) {
+                    if z > args.z_score {
+                        writeln!(
+                            bed,
+                            "{}\t{}\t{}\t{z:.2}",
+                            window.seqid, window.start, window.end
+                        )?;
+                    }
+                }
+            }
+        }
+        Commands::Screen(args) => {
+            let records = utils::read_records(&args.assembly)?;
+            if records.is_empty() {
+                anyhow::bail!("No sequence found in {}", args.assembly.display());
+            }
+            let database = match &args.db {
+                Some(path) => Some(db::Database::read_from(BufReader::new(File::open(path)?))?),
+                None => None,
+            };
+            let k = database.as_ref().map_or(args.k, |db| db.k);
+            let screen = screen::Screen::new(
+                &records,
+                k,
+                args.min_length,
+                args.z_score,
+                database.as_ref(),
+            );
+
+            match &args.output {
+                Some(path) => screen.write_all(BufWriter::new(File::create(path)?), args.all)?,
+                None => screen.write_all(io::stdout().lock(), args.all)?,
             }
         }
+        Commands::Cluster(args) => {
+            let mut records = Vec::new();
+            for file in &args.files {
+                records.extend(utils::read_records(file)?);
+            }
+
+            let signatures: Vec<fcgr::Fcgr> = records
+                .par_iter()
+                .map(|record| fcgr::Fcgr::from_sequence(record.sequence().as_ref(), args.k))
+                .collect();
+            let distances = cluster::DistanceMatrix::from_signatures(&signatures);
+
+            // clap does not require the arguments of the default method
+            let missing = |argument: &str| {
+                anyhow::anyhow!(
+                    "--{argument} is required by the {} method",
+                    args.method
+                        .to_possible_value()
+                        .expect("no skipped method")
+                        .get_name()
+                )
+            };
+            let clustering = match args.method {
+                cluster::ClusterMethod::Kmedoids => {
+                    let clusters = args.clusters.ok_or_else(|| missing("clusters"))?;
+                    cluster::kmedoids(&distances, clusters as usize)
+                }
+                cluster::ClusterMethod::Hierarchical => {
+                    let threshold = args.threshold.ok_or_else(|| missing("threshold"))?;
+                    cluster::hierarchical(&distances, threshold, args.linkage)
+                }
+                cluster::ClusterMethod::Dbscan => {
+                    let eps = args.eps.ok_or_else(|| missing("eps"))?;
+                    cluster::dbscan(&distances, eps, args.min_points as usize)
+                }
+            };
+
+            let mut destination: Box<dyn Write> = if let Some(out) = args.output {
+                Box::new(File::create(out)?)
+            } else {
+                Box::new(io::stdout().lock())
+            };
+            writeln!(destination, "#id\tcluster\tmedoid")?;
+            for (record, assignment) in records.iter().zip(&clustering.assignments) {
+                match assignment {
+                    Some(c) => writeln!(
+                        destination,
+                        "{}\t{}\t{}",
+                        record.name(),
+                        c,
+                        records[clustering.medoids[*c]].name()
+                    )?,
+                    None => writeln!(destination, "{}\tnoise\t-", record.name())?,
+                }
+            }
+
+            if let Some(representatives) = args.representatives {
+                let mut writer = fasta::Writer::new(BufWriter::new(File::create(representatives)?));
+                for &medoid in &clustering.medoids {
+                    writer.write_record(&records[medoid])?;
+                }
+            }
+        }
+        Commands::Db(DbCommands::Build(args)) => {
+            let mut references: Vec<String> = args
+                .files
+                .iter()
+                .map(|f| f.to_string_lossy().into_owned())
+                .collect();
+            if let Some(ref_file) = &args.refs {
+                references.extend(utils::read_lines(ref_file)?.map_while(Result::ok));
+            }
+            if references.is_empty() {
+                anyhow::bail!("No reference sequence supplied");
+            }
+
+            let database = db::Database::build(&references, args.k, args.canonical)?;
+            let mut writer = BufWriter::new(File::create(args.output)?);
+            database.write_all(&mut writer)?;
+            writer.flush()?;
+        }
     }
 
     Ok(())
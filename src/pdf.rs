@@ -0,0 +1,424 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use plotters_backend::text_anchor::{HPos, VPos};
+use plotters_backend::{
+    BackendColor, BackendCoord, BackendStyle, BackendTextStyle, DrawingBackend, DrawingErrorKind,
+};
+
+/// Bézier control point distance approximating a quarter circle of radius 1
+const KAPPA: f64 = 0.552_284_749_8;
+
+/// Average glyph width of Helvetica, relative to the font size
+const GLYPH_WIDTH: f64 = 0.55;
+
+/// Drawing backend writing a single-page vector PDF.
+///
+/// Coordinates are in pixels with the origin at the top-left corner, like the
+/// other backends; the page is sized so that `dpi` pixels make an inch. Text
+/// uses the standard Helvetica font, which PDF readers provide, so no font is
+/// embedded.
+pub struct PdfBackend {
+    path: PathBuf,
+    size: (u32, u32),
+    dpi: u32,
+
+    /// Page content stream
+    content: String,
+
+    /// Opacities used, each one being an entry of the page graphics states
+    alphas: Vec<f64>,
+
+    /// Current fill and stroke paint, to avoid repeating operators
+    fill: Option<(u8, u8, u8, usize)>,
+    stroke: Option<(u8, u8, u8, usize)>,
+    line_width: Option<u32>,
+
+    saved: bool,
+}
+
+impl PdfBackend {
+    /// Creates a PDF of `size` pixels saved to `path` when presented
+    pub fn new<P: AsRef<Path>>(path: P, size: (u32, u32), dpi: u32) -> Self {
+        PdfBackend {
+            path: path.as_ref().to_path_buf(),
+            size,
+            dpi,
+            content: String::new(),
+            alphas: Vec::new(),
+            fill: None,
+            stroke: None,
+            line_width: None,
+            saved: false,
+        }
+    }
+
+    /// Index of the graphics state setting an opacity
+    fn alpha_state(&mut self, alpha: f64) -> usize {
+        let alpha = (alpha.clamp(0.0, 1.0) * 1000.0).round() / 1000.0;
+        match self.alphas.iter().position(|&a| a == alpha) {
+            Some(i) => i,
+            None => {
+                self.alphas.push(alpha);
+                self.alphas.len() - 1
+            }
+        }
+    }
+
+    fn set_fill(&mut self, color: BackendColor) {
+        let (r, g, b) = color.rgb;
+        let gs = self.alpha_state(color.alpha);
+        let paint = Some((r, g, b, gs));
+        if self.fill != paint {
+            let _ = writeln!(self.content, "{} rg /GS{gs} gs", rgb(color));
+            self.fill = paint;
+            // The graphics state sets both opacities
+            self.stroke = None;
+        }
+    }
+
+    fn set_stroke(&mut self, color: BackendColor, width: u32) {
+        let (r, g, b) = color.rgb;
+        let gs = self.alpha_state(color.alpha);
+        let paint = Some((r, g, b, gs));
+        if self.stroke != paint {
+            let _ = writeln!(self.content, "{} RG /GS{gs} gs", rgb(color));
+            self.stroke = paint;
+            self.fill = None;
+        }
+        if self.line_width != Some(width) {
+            let _ = writeln!(self.content, "{width} w");
+            self.line_width = Some(width);
+        }
+    }
+
+    /// Serialises the document
+    fn document(&self) -> Vec<u8> {
+        let scale = 72.0 / f64::from(self.dpi.max(1));
+        let (width, height) = (
+            f64::from(self.size.0) * scale,
+            f64::from(self.size.1) * scale,
+        );
+
+        // Flip the y axis so that content is written in pixel coordinates
+        let content = format!(
+            "{scale:.6} 0 0 {:.6} 0 {height:.3} cm\n{}",
+            -scale, self.content
+        );
+        let states: String = self
+            .alphas
+            .iter()
+            .enumerate()
+            .map(|(i, a)| format!("/GS{i} << /ca {a} /CA {a} >> "))
+            .collect();
+
+        let objects = [
+            String::from("<< /Type /Catalog /Pages 2 0 R >>"),
+            String::from("<< /Type /Pages /Kids [3 0 R] /Count 1 >>"),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {width:.3} {height:.3}] \
+                 /Resources << /Font << /F1 4 0 R >> /ExtGState << {states}>> >> \
+                 /Contents 5 0 R >>"
+            ),
+            String::from(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica \
+                 /Encoding /WinAnsiEncoding >>",
+            ),
+            format!(
+                "<< /Length {} >>\nstream\n{content}endstream",
+                content.len()
+            ),
+        ];
+
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            let _ = write!(pdf, "{} 0 obj\n{object}\nendobj\n", i + 1);
+        }
+        let xref = pdf.len();
+        let _ = write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(pdf, "{offset:010} 00000 n ");
+        }
+        let _ = write!(
+            pdf,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        );
+        pdf.into_bytes()
+    }
+}
+
+/// PDF colour operands of a colour
+fn rgb(color: BackendColor) -> String {
+    let (r, g, b) = color.rgb;
+    let c = |v: u8| f64::from(v) / 255.0;
+    format!("{:.3} {:.3} {:.3}", c(r), c(g), c(b))
+}
+
+/// Escapes text for a PDF string, replacing characters Helvetica cannot show
+fn pdf_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+impl DrawingBackend for PdfBackend {
+    type ErrorType = io::Error;
+
+    fn get_size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn ensure_prepared(&mut self) -> Result<(), DrawingErrorKind<io::Error>> {
+        Ok(())
+    }
+
+    fn present(&mut self) -> Result<(), DrawingErrorKind<io::Error>> {
+        fs::write(&self.path, self.document()).map_err(DrawingErrorKind::DrawingError)?;
+        self.saved = true;
+        Ok(())
+    }
+
+    fn draw_pixel(
+        &mut self,
+        point: BackendCoord,
+        color: BackendColor,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if color.alpha == 0.0 {
+            return Ok(());
+        }
+        self.set_fill(color);
+        let _ = writeln!(self.content, "{} {} 1 1 re f", point.0, point.1);
+        Ok(())
+    }
+
+    fn draw_line<S: BackendStyle>(
+        &mut self,
+        from: BackendCoord,
+        to: BackendCoord,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        self.draw_path([from, to], style)
+    }
+
+    fn draw_rect<S: BackendStyle>(
+        &mut self,
+        upper_left: BackendCoord,
+        bottom_right: BackendCoord,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        let color = style.color();
+        if color.alpha == 0.0 {
+            return Ok(());
+        }
+        let (x, y) = upper_left;
+        let (w, h) = (bottom_right.0 - x, bottom_right.1 - y);
+        if fill {
+            self.set_fill(color);
+            let _ = writeln!(self.content, "{x} {y} {w} {h} re f");
+        } else {
+            self.set_stroke(color, style.stroke_width());
+            let _ = writeln!(
+                self.content,
+                "{} {} {w} {h} re S",
+                x as f64 + 0.5,
+                y as f64 + 0.5
+            );
+        }
+        Ok(())
+    }
+
+    fn draw_path<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        path: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        let color = style.color();
+        if color.alpha == 0.0 {
+            return Ok(());
+        }
+        self.set_stroke(color, style.stroke_width());
+        let mut ops = "m";
+        for (x, y) in path {
+            // Centre lines on pixels, as rasterised lines are
+            let _ = write!(self.content, "{} {} {ops} ", x as f64 + 0.5, y as f64 + 0.5);
+            ops = "l";
+        }
+        self.content.push_str("S\n");
+        Ok(())
+    }
+
+    fn draw_circle<S: BackendStyle>(
+        &mut self,
+        center: BackendCoord,
+        radius: u32,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        let color = style.color();
+        if color.alpha == 0.0 {
+            return Ok(());
+        }
+        if fill {
+            self.set_fill(color);
+        } else {
+            self.set_stroke(color, style.stroke_width());
+        }
+
+        let (cx, cy) = (f64::from(center.0), f64::from(center.1));
+        let r = f64::from(radius);
+        let k = r * KAPPA;
+        let _ = write!(self.content, "{} {cy} m ", cx + r);
+        // One cubic Bézier per quarter, counterclockwise from the rightmost point
+        for [(x1, y1), (x2, y2), (x3, y3)] in [
+            [(r, k), (k, r), (0.0, r)],
+            [(-k, r), (-r, k), (-r, 0.0)],
+            [(-r, -k), (-k, -r), (0.0, -r)],
+            [(k, -r), (r, -k), (r, 0.0)],
+        ] {
+            let _ = write!(
+                self.content,
+                "{:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c ",
+                cx + x1,
+                cy + y1,
+                cx + x2,
+                cy + y2,
+                cx + x3,
+                cy + y3
+            );
+        }
+        self.content.push_str(if fill { "f\n" } else { "S\n" });
+        Ok(())
+    }
+
+    fn fill_polygon<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        vert: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        let color = style.color();
+        if color.alpha == 0.0 {
+            return Ok(());
+        }
+        self.set_fill(color);
+        let mut ops = "m";
+        for (x, y) in vert {
+            let _ = write!(self.content, "{x} {y} {ops} ");
+            ops = "l";
+        }
+        self.content.push_str("h f\n");
+        Ok(())
+    }
+
+    fn draw_text<TStyle: BackendTextStyle>(
+        &mut self,
+        text: &str,
+        style: &TStyle,
+        pos: BackendCoord,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        let color = style.color();
+        if color.alpha == 0.0 {
+            return Ok(());
+        }
+        let (width, _) = self.estimate_text_size(text, style)?;
+        let size = style.size();
+        let anchor = style.anchor();
+        let x = f64::from(pos.0)
+            - match anchor.h_pos {
+                HPos::Left => 0.0,
+                HPos::Center => f64::from(width) / 2.0,
+                HPos::Right => f64::from(width),
+            };
+        // Place the baseline from the approximate ascent and descent of Helvetica
+        let y = f64::from(pos.1)
+            + match anchor.v_pos {
+                VPos::Top => 0.75 * size,
+                VPos::Center => 0.35 * size,
+                VPos::Bottom => -0.2 * size,
+            };
+
+        self.set_fill(color);
+        // Text is flipped back upright in the y-down page coordinates
+        let _ = writeln!(
+            self.content,
+            "BT /F1 {size:.2} Tf 1 0 0 -1 {x:.2} {y:.2} Tm ({}) Tj ET",
+            pdf_string(text)
+        );
+        Ok(())
+    }
+
+    fn estimate_text_size<TStyle: BackendTextStyle>(
+        &self,
+        text: &str,
+        style: &TStyle,
+    ) -> Result<(u32, u32), DrawingErrorKind<io::Error>> {
+        let size = style.size();
+        let width = text.chars().count() as f64 * GLYPH_WIDTH * size;
+        Ok((width.round() as u32, size.round() as u32))
+    }
+}
+
+impl Drop for PdfBackend {
+    fn drop(&mut self) {
+        if !self.saved {
+            // Like the other file backends, save on drop if not presented
+            let _ = self.present();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plotters::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_pdf_document_structure() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("plot.pdf");
+        {
+            let root = PdfBackend::new(&path, (200, 100), 144).into_drawing_area();
+            root.fill(&WHITE).unwrap();
+            root.draw(&Circle::new((50, 50), 10, RED.mix(0.5).filled()))
+                .unwrap();
+            root.draw(&Text::new("A (x)", (10, 10), ("sans-serif", 12)))
+                .unwrap();
+            root.present().unwrap();
+        }
+
+        let pdf = std::fs::read_to_string(&path).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+        // 144 dpi pixels make half points
+        assert!(pdf.contains("/MediaBox [0 0 100.000 50.000]"));
+        assert!(pdf.contains("/GS1 << /ca 0.5 /CA 0.5 >>"));
+        assert!(pdf.contains("(A \\(x\\)) Tj"));
+
+        // Cross-reference offsets point at their objects
+        let xref = pdf.rfind("startxref\n").unwrap() + "startxref\n".len();
+        let start: usize = pdf[xref..].lines().next().unwrap().parse().unwrap();
+        assert!(pdf[start..].starts_with("xref\n0 6\n"));
+        let offset: usize = pdf[start..].lines().nth(5).unwrap()[..10].parse().unwrap();
+        assert!(pdf[offset..].starts_with("3 0 obj"));
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use clap::ValueEnum;
+
+/// Amino acid alphabets of protein CGRs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProteinAlphabet {
+    /// The 20 amino acids, each on its own vertex
+    Full,
+    /// 4 groups of the detailed HP model: nonpolar, negatively charged,
+    /// uncharged polar and positively charged (Yu et al., 2004)
+    Hp,
+    /// 6 groups of Dayhoff's exchange classes
+    Dayhoff,
+    /// 10 groups of Murphy et al. (2000)
+    Murphy10,
+}
+
+impl ProteinAlphabet {
+    /// Groups of amino acids sharing a vertex, in vertex order
+    pub fn groups(&self) -> &'static [&'static str] {
+        match self {
+            ProteinAlphabet::Full => &[
+                "A", "R", "N", "D", "C", "Q", "E", "G", "H", "I", "L", "K", "M", "F", "P", "S",
+                "T", "W", "Y", "V",
+            ],
+            ProteinAlphabet::Hp => &["AFGILMPVW", "DE", "CNQSTY", "HKR"],
+            ProteinAlphabet::Dayhoff => &["AGPST", "C", "DENQ", "FWY", "HKR", "ILMV"],
+            ProteinAlphabet::Murphy10 => {
+                &["LVIM", "C", "A", "G", "ST", "P", "FYW", "EDNQ", "KR", "H"]
+            }
+        }
+    }
+}
+
+/// Whether a sequence looks like a protein rather than nucleic acids, i.e.
+/// more than a tenth of its letters are not A, C, G, T, U or N
+pub fn is_protein(sequence: &[u8]) -> bool {
+    let (letters, other) = sequence.iter().filter(|b| b.is_ascii_alphabetic()).fold(
+        (0usize, 0usize),
+        |(letters, other), b| match b.to_ascii_uppercase() {
+            b'A' | b'C' | b'G' | b'T' | b'U' | b'N' => (letters + 1, other),
+            _ => (letters + 1, other + 1),
+        },
+    );
+    other * 10 > letters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups_partition_amino_acids() {
+        for alphabet in ProteinAlphabet::value_variants() {
+            let mut residues: Vec<u8> = alphabet.groups().concat().into_bytes();
+            residues.sort();
+            assert_eq!(residues, b"ACDEFGHIKLMNPQRSTVWY", "{alphabet:?}");
+        }
+    }
+
+    #[test]
+    fn test_is_protein() {
+        assert!(!is_protein(b"ACGTNNacgtu"));
+        assert!(is_protein(b"MKVLAAGIVGLLLAQ"));
+        assert!(!is_protein(b""));
+    }
+}
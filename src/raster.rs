@@ -0,0 +1,502 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Built-in rasteriser drawing CGRs without any font or system library.
+//!
+//! Pixels are blended with integer arithmetic and do not depend on font
+//! rendering. Logarithmic density scales are computed in fixed point and
+//! polygon vertices from series of exactly rounded operations, never from the
+//! platform math library, so that a given sequence and set of options produce
+//! byte-identical images on every platform. Text is limited to letters,
+//! digits, `*` and `-`, drawn from bitmap glyphs; axes have no tick labels.
+
+use anyhow::{bail, Result};
+use plotters::style::RGBColor;
+
+use crate::alphabet::Alphabet;
+use crate::cgr::{self, DrawOptions, LEGEND_WIDTH};
+use crate::render::{self, DrawMode};
+
+/// Colour of the mesh lines
+const MESH: RGBColor = RGBColor(0xe0, 0xe0, 0xe0);
+
+/// Number of mesh divisions along each axis
+const MESH_DIVISIONS: i64 = 10;
+
+/// 5 x 7 glyphs of the labels, one row per byte from the top, leftmost column
+/// in bit 4; lowercase letters are drawn as uppercase
+const GLYPHS: [(char, [u8; 7]); 38] = [
+    ('A', [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11]),
+    ('B', [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e]),
+    ('C', [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e]),
+    ('D', [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c]),
+    ('E', [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f]),
+    ('F', [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10]),
+    ('G', [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f]),
+    ('H', [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11]),
+    ('I', [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f]),
+    ('M', [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e]),
+    ('P', [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10]),
+    ('Q', [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d]),
+    ('R', [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11]),
+    ('S', [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e]),
+    ('T', [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a]),
+    ('X', [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04]),
+    ('Z', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f]),
+    ('0', [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e]),
+    ('1', [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    ('2', [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f]),
+    ('3', [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e]),
+    ('4', [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02]),
+    ('5', [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e]),
+    ('6', [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e]),
+    ('7', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e]),
+    ('9', [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c]),
+    ('*', [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00]),
+    ('-', [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00]),
+];
+
+/// RGB image buffer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    /// Creates an image filled with a colour
+    pub fn new(width: u32, height: u32, background: RGBColor) -> Self {
+        let pixels =
+            [background.0, background.1, background.2].repeat(width as usize * height as usize);
+        Canvas {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// RGB bytes of the image, row by row from the top
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Blends a colour over a pixel with an opacity out of 255, ignoring
+    /// pixels outside the image
+    fn blend(&mut self, x: i64, y: i64, colour: RGBColor, alpha: u8) {
+        if x < 0 || y < 0 || x >= i64::from(self.width) || y >= i64::from(self.height) {
+            return;
+        }
+        let at = (y as usize * self.width as usize + x as usize) * 3;
+        let alpha = u32::from(alpha);
+        for (pixel, c) in self.pixels[at..at + 3]
+            .iter_mut()
+            .zip([colour.0, colour.1, colour.2])
+        {
+            *pixel = ((u32::from(c) * alpha + u32::from(*pixel) * (255 - alpha) + 127) / 255) as u8;
+        }
+    }
+
+    /// Fills the rectangle `[x0, x1) x [y0, y1)`
+    fn fill_rect(&mut self, (x0, y0): (i64, i64), (x1, y1): (i64, i64), colour: RGBColor) {
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.blend(x, y, colour, 255);
+            }
+        }
+    }
+
+    /// Draws the outline of the rectangle `[x0, x1) x [y0, y1)`
+    fn stroke_rect(&mut self, (x0, y0): (i64, i64), (x1, y1): (i64, i64), colour: RGBColor) {
+        self.fill_rect((x0, y0), (x1, y0 + 1), colour);
+        self.fill_rect((x0, y1 - 1), (x1, y1), colour);
+        self.fill_rect((x0, y0), (x0 + 1, y1), colour);
+        self.fill_rect((x1 - 1, y0), (x1, y1), colour);
+    }
+
+    /// Fills the pixels within `radius` of a centre
+    fn fill_disc(&mut self, (cx, cy): (i64, i64), radius: i64, colour: RGBColor, alpha: u8) {
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx * dx + dy * dy <= radius * radius {
+                    self.blend(cx + dx, cy + dy, colour, alpha);
+                }
+            }
+        }
+    }
+
+    /// Draws a glyph with its top-left corner at a position
+    fn draw_glyph(
+        &mut self,
+        label: char,
+        (x, y): (i64, i64),
+        scale: i64,
+        colour: RGBColor,
+    ) -> Result<()> {
+        let upper = label.to_ascii_uppercase();
+        let Some((_, rows)) = GLYPHS.iter().find(|(c, _)| *c == upper) else {
+            bail!("The built-in rasteriser has no glyph for the label '{label}'");
+        };
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..5 {
+                if bits & (0x10 >> col) != 0 {
+                    let (gx, gy) = (x + col * scale, y + row as i64 * scale);
+                    self.fill_rect((gx, gy), (gx + scale, gy + scale), colour);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws a string centred on a position, glyphs being separated by one
+    /// glyph pixel
+    fn draw_text(
+        &mut self,
+        text: &str,
+        (cx, cy): (i64, i64),
+        scale: i64,
+        colour: RGBColor,
+    ) -> Result<()> {
+        let width = (6 * text.chars().count() as i64 - 1) * scale;
+        let (x, y) = (cx - width / 2, cy - 7 * scale / 2);
+        for (i, label) in text.chars().enumerate() {
+            self.draw_glyph(label, (x + 6 * i as i64 * scale, y), scale, colour)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pixel of a CGR coordinate in [-1, 1] along an axis of `extent` pixels
+fn to_pixel(value: f64, extent: u32) -> i64 {
+    ((value + 1.0) / 2.0 * f64::from(extent.saturating_sub(1))).round() as i64
+}
+
+/// Side of a vertex coordinate: -1, 0 or 1
+fn side(value: f64) -> i64 {
+    if value > 0.1 {
+        1
+    } else if value < -0.1 {
+        -1
+    } else {
+        0
+    }
+}
+
+/// Renders coloured CGR points, drawn in order, with the given options.
+///
+/// Corner labels are the first symbol of each vertex, placed inside the plot
+/// on the side of the vertex. Labels with a character that has no glyph are
+/// an error.
+pub fn render(
+    points: &[((f64, f64), RGBColor)],
+    options: &DrawOptions,
+    alphabet: &Alphabet,
+) -> Result<Canvas> {
+    let (width, height) = options.dimensions();
+    let mut canvas = Canvas::new(width, height, options.background);
+
+    let legend = options.mode == DrawMode::Density && !options.no_legend;
+    let plot_width = if legend {
+        width.saturating_sub(LEGEND_WIDTH)
+    } else {
+        width
+    };
+    let (pw, ph) = (i64::from(plot_width), i64::from(height));
+
+    if !options.no_mesh {
+        for i in 1..MESH_DIVISIONS {
+            let x = i * (pw - 1) / MESH_DIVISIONS;
+            let y = i * (ph - 1) / MESH_DIVISIONS;
+            canvas.fill_rect((x, 0), (x + 1, ph), MESH);
+            canvas.fill_rect((0, y), (pw, y + 1), MESH);
+        }
+    }
+
+    match options.mode {
+        DrawMode::Points => {
+            let alpha = (options.opacity * 255.0).round() as u8;
+            for &((x, y), colour) in points {
+                let centre = (to_pixel(x, plot_width), to_pixel(-y, height));
+                canvas.fill_disc(centre, i64::from(options.point_size), colour, alpha);
+            }
+        }
+        DrawMode::Density => {
+            let coords: Vec<(f64, f64)> = points.iter().map(|&(point, _)| point).collect();
+            let grid = render::bin_points(&coords, plot_width as usize, height as usize);
+            let max = grid.iter().copied().max().unwrap_or(0);
+            for (i, &count) in grid.iter().enumerate().filter(|(_, &c)| c > 0) {
+                let colour = options
+                    .colour_map
+                    .colour(options.scale.normalise(count, max));
+                let (x, y) = (
+                    (i % plot_width as usize) as i64,
+                    (i / plot_width as usize) as i64,
+                );
+                canvas.blend(x, y, colour, 255);
+            }
+
+            if legend {
+                // Unlabelled colour bar, laid out as with the plotting backends
+                let (left, right) = (pw + 10, pw + 30);
+                let (top, bottom) = (10, ph - 10);
+                let span = (bottom - top).max(1);
+                for y in top..bottom {
+                    let colour = options.colour_map.colour((bottom - y) as f64 / span as f64);
+                    canvas.fill_rect((left, y), (right, y + 1), colour);
+                }
+                canvas.stroke_rect((left, top), (right + 1, bottom + 1), RGBColor(0, 0, 0));
+            }
+        }
+    }
+
+    let overlay = cgr::kmer_overlay(options, alphabet);
+    let motif_alpha = (0.35f64 * 255.0).round() as u8;
+    for &((x, y), half) in &overlay.motifs {
+        let (x0, x1) = (
+            to_pixel(x - half, plot_width),
+            to_pixel(x + half, plot_width),
+        );
+        let (y0, y1) = (to_pixel(-y - half, height), to_pixel(-y + half, height));
+        for py in y0..y1 {
+            for px in x0..x1 {
+                canvas.blend(px, py, options.motif_colour, motif_alpha);
+            }
+        }
+    }
+    let grid_alpha = (0.6f64 * 255.0).round() as u8;
+    for &at in &overlay.lines {
+        let (x, y) = (to_pixel(at, plot_width), to_pixel(-at, height));
+        for py in 0..ph {
+            canvas.blend(x, py, RGBColor(0, 0, 0), grid_alpha);
+        }
+        for px in 0..pw {
+            canvas.blend(px, y, RGBColor(0, 0, 0), grid_alpha);
+        }
+    }
+    if let Some(k) = options.kmer_grid {
+        let cell = pw.min(ph) >> k;
+        let scale = cell * 4 / 5 / (6 * i64::from(k));
+        if scale > 0 {
+            for (kmer, (x, y)) in &overlay.labels {
+                let centre = (to_pixel(*x, plot_width), to_pixel(-y, height));
+                canvas.draw_text(kmer, centre, scale, RGBColor(0, 0, 0))?;
+            }
+        }
+    }
+
+    if !options.no_axes {
+        canvas.stroke_rect((0, 0), (pw, ph), RGBColor(0, 0, 0));
+    }
+
+    if options.corner_labels {
+        // Same font sizes as with the plotting backends
+        let font = i64::from(height) / if alphabet.is_square() { 32 } else { 48 };
+        let scale = font.max(12) / 7;
+        let (gw, gh) = (5 * scale, 7 * scale);
+        let margin = 4;
+        for (symbols, (x, y)) in alphabet.vertices() {
+            let (px, py) = (to_pixel(*x, plot_width), to_pixel(-y, height));
+            let position = (
+                match side(*x) {
+                    1 => px + 1 - margin - gw,
+                    -1 => px + margin,
+                    _ => px - gw / 2,
+                },
+                match side(*y) {
+                    1 => py + margin,
+                    -1 => py + 1 - margin - gh,
+                    _ => py - gh / 2,
+                },
+            );
+            let Some(label) = symbols.chars().next() else {
+                continue;
+            };
+            canvas.draw_glyph(label, position, scale, RGBColor(0, 0, 0))?;
+        }
+    }
+
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protein::ProteinAlphabet;
+    use crate::render::Scale;
+
+    const BLACK: RGBColor = RGBColor(0, 0, 0);
+
+    fn pixel(canvas: &Canvas, x: usize, y: usize) -> [u8; 3] {
+        let at = (y * canvas.width as usize + x) * 3;
+        [
+            canvas.pixels[at],
+            canvas.pixels[at + 1],
+            canvas.pixels[at + 2],
+        ]
+    }
+
+    #[test]
+    fn test_render_points() {
+        let dna = Alphabet::nucleotides();
+        let options = DrawOptions {
+            size: 101,
+            point_size: 1,
+            no_mesh: true,
+            no_axes: true,
+            ..Default::default()
+        };
+        // The A corner quadrant centre, and the centre of the square
+        let canvas = render(&[((0.5, 0.5), BLACK), ((0.0, 0.0), BLACK)], &options, &dna).unwrap();
+        assert_eq!(pixel(&canvas, 75, 25), [0, 0, 0]);
+        assert_eq!(pixel(&canvas, 76, 25), [0, 0, 0]);
+        assert_eq!(pixel(&canvas, 76, 26), [255, 255, 255]);
+        assert_eq!(pixel(&canvas, 50, 50), [0, 0, 0]);
+        assert_eq!(pixel(&canvas, 25, 75), [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_render_opacity_blending() {
+        let dna = Alphabet::nucleotides();
+        let options = DrawOptions {
+            size: 32,
+            point_size: 0,
+            opacity: 0.5,
+            no_mesh: true,
+            no_axes: true,
+            ..Default::default()
+        };
+        let canvas = render(&[((0.0, 0.0), BLACK)], &options, &dna).unwrap();
+        assert_eq!(pixel(&canvas, 16, 16), [127, 127, 127]);
+
+        // Overlapping points darken the pixel
+        let canvas = render(&[((0.0, 0.0), BLACK), ((0.0, 0.0), BLACK)], &options, &dna).unwrap();
+        assert_eq!(pixel(&canvas, 16, 16), [63, 63, 63]);
+    }
+
+    #[test]
+    fn test_render_kmer_overlay() {
+        let dna = Alphabet::nucleotides();
+        let options = DrawOptions {
+            size: 129,
+            no_mesh: true,
+            no_axes: true,
+            kmer_grid: Some(1),
+            motif: vec![String::from("T")],
+            motif_colour: RGBColor(255, 0, 0),
+            ..Default::default()
+        };
+        let canvas = render(&[], &options, &dna).unwrap();
+        // Grid lines cross at the centre, the T quadrant is shaded
+        assert_eq!(pixel(&canvas, 64, 10), [102, 102, 102]);
+        assert_eq!(pixel(&canvas, 10, 100), [255, 255, 255]);
+        assert_eq!(pixel(&canvas, 10, 10), [255, 166, 166]);
+        // The A quadrant label is centred on its cell
+        assert_eq!(pixel(&canvas, 96, 32), [0, 0, 0]);
+    }
+
+    /// Points of the chaos game played on a pseudo-random sequence
+    fn chaos_points(alphabet: &Alphabet, length: usize) -> Vec<((f64, f64), RGBColor)> {
+        let (mut state, mut point) = (42u64, (0.0, 0.0));
+        (0..length)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let vertices = alphabet.vertices();
+                let (_, (vx, vy)) = vertices[(state >> 33) as usize % vertices.len()];
+                point = (
+                    point.0 + alphabet.ratio() * (vx - point.0),
+                    point.1 + alphabet.ratio() * (vy - point.1),
+                );
+                (point, BLACK)
+            })
+            .collect()
+    }
+
+    /// 64-bit FNV-1a hash of the pixels
+    fn checksum(canvas: &Canvas) -> u64 {
+        canvas
+            .pixels()
+            .iter()
+            .fold(0xcbf29ce484222325, |hash, &byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    #[test]
+    fn test_render_is_deterministic() {
+        let dna = Alphabet::nucleotides();
+        let points = chaos_points(&dna, 500);
+        let options = DrawOptions {
+            size: 128,
+            corner_labels: true,
+            mode: DrawMode::Density,
+            ..Default::default()
+        };
+        let canvas = render(&points, &options, &dna).unwrap();
+        assert_eq!(canvas, render(&points, &options, &dna).unwrap());
+        assert_eq!(canvas.pixels().len(), 128 * 128 * 3);
+        // Frame and corner label
+        assert_eq!(pixel(&canvas, 0, 64), [0, 0, 0]);
+        assert_eq!(pixel(&canvas, 4, 4), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_render_golden_checksums() {
+        // Images must be byte-identical on every platform
+        let dna = Alphabet::nucleotides();
+        let options = DrawOptions {
+            size: 256,
+            corner_labels: true,
+            kmer_grid: Some(2),
+            ..Default::default()
+        };
+        let canvas = render(&chaos_points(&dna, 2000), &options, &dna).unwrap();
+        assert_eq!(checksum(&canvas), 5430945609937738219);
+
+        let protein = Alphabet::protein(ProteinAlphabet::Full);
+        let options = DrawOptions {
+            size: 300,
+            corner_labels: true,
+            mode: DrawMode::Density,
+            scale: Scale::Log,
+            ..Default::default()
+        };
+        let canvas = render(&chaos_points(&protein, 5000), &options, &protein).unwrap();
+        assert_eq!(checksum(&canvas), 12082068010296191173);
+    }
+
+    #[test]
+    fn test_render_corner_labels() {
+        let options = DrawOptions {
+            size: 200,
+            corner_labels: true,
+            no_mesh: true,
+            no_axes: true,
+            ..Default::default()
+        };
+        // The bottom vertex of a triangle is labelled above it, centred
+        let alphabet = Alphabet::new(&["A", "B", "C"]).unwrap();
+        let canvas = render(&[], &options, &alphabet).unwrap();
+        let bottom: Vec<usize> = (0..200)
+            .filter(|&x| (170..200).any(|y| pixel(&canvas, x, y) == [0, 0, 0]))
+            .collect();
+        assert_eq!((bottom[0], bottom[bottom.len() - 1]), (98, 102));
+
+        // Symbols without a glyph are an error rather than silently skipped
+        let alphabet = Alphabet::new(&["A", "?", "C"]).unwrap();
+        assert!(render(&[], &options, &alphabet).is_err());
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::path::Path;
+
+use clap::ValueEnum;
+use plotters::style::RGBColor;
+
+/// How CGR points are rendered
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum DrawMode {
+    /// Each point is drawn as a circle
+    Points,
+    /// Points are binned into pixels and counts are drawn as a heatmap
+    Density,
+}
+
+/// Scaling of counts before colouring
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Scale {
+    /// Colour proportional to the count
+    Linear,
+    /// Colour proportional to the logarithm of the count
+    Log,
+}
+
+impl Scale {
+    /// Maps a count to [0, 1] given the largest count
+    pub fn normalise(&self, count: u64, max: u64) -> f64 {
+        if max == 0 {
+            return 0.0;
+        }
+        match self {
+            Scale::Linear => count as f64 / max as f64,
+            Scale::Log => {
+                log2_fixed(count.saturating_add(1)) as f64
+                    / log2_fixed(max.saturating_add(1)) as f64
+            }
+        }
+    }
+
+    /// Count mapped to a value in [0, 1] given the largest count
+    pub fn count_at(&self, value: f64, max: u64) -> f64 {
+        match self {
+            Scale::Linear => value * max as f64,
+            Scale::Log => {
+                let exponent = value.clamp(0.0, 1.0) * log2_fixed(max.saturating_add(1)) as f64;
+                exp2_fixed(exponent.round() as u64) - 1.0
+            }
+        }
+    }
+}
+
+/// Fractional bits of the fixed-point logarithms of the log scale
+const LOG_BITS: u32 = 46;
+
+/// Base-2 logarithm of n >= 1 with `LOG_BITS` fractional bits.
+///
+/// Only integer arithmetic is used, so that log-scaled images do not depend
+/// on the math library of the platform: each fractional bit is read by
+/// squaring the mantissa, kept in [1, 2) with 62 fractional bits.
+fn log2_fixed(n: u64) -> u64 {
+    let integer = 63 - n.leading_zeros();
+    let mut mantissa = (u128::from(n) << 62) >> integer;
+    let mut log = u64::from(integer) << LOG_BITS;
+    for bit in (0..LOG_BITS).rev() {
+        mantissa = (mantissa * mantissa) >> 62;
+        if mantissa >> 63 != 0 {
+            mantissa >>= 1;
+            log |= 1 << bit;
+        }
+    }
+    log
+}
+
+/// Integer square root, rounded down
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = 1u128 << (128 - n.leading_zeros()).div_ceil(2);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/// Power of two of an exponent with `LOG_BITS` fractional bits, the inverse
+/// of `log2_fixed`: each fractional bit multiplies the mantissa by 2^(2^-i),
+/// a repeated integer square root of two.
+fn exp2_fixed(exponent: u64) -> f64 {
+    let one = 1u128 << 62;
+    let (mut mantissa, mut root) = (one, 2 * one);
+    for bit in (0..LOG_BITS).rev() {
+        root = isqrt(root << 62);
+        if (exponent >> bit) & 1 == 1 {
+            mantissa = (mantissa * root) >> 62;
+        }
+    }
+    // Exact power of two 2^(integer - 62)
+    let scale = f64::from_bits((1023 - 62 + (exponent >> LOG_BITS)) << 52);
+    mantissa as f64 * scale
+}
+
+/// Colour maps for heatmaps
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ColourMap {
+    /// Perceptually uniform, dark blue to yellow
+    Viridis,
+    /// Perceptually uniform, black to light yellow through purple
+    Magma,
+    /// Perceptually uniform, black to light yellow through red
+    Inferno,
+    /// Light grey to black
+    Greys,
+    /// Black to white through red and yellow
+    Hot,
+    /// Blue to red through white, for signed values centred on 0.5
+    Diverging,
+}
+
+impl ColourMap {
+    /// Colours the map interpolates between, from low to high values
+    fn anchors(&self) -> &'static [(u8, u8, u8)] {
+        match self {
+            ColourMap::Viridis => &[
+                (0x44, 0x01, 0x54),
+                (0x3b, 0x52, 0x8b),
+                (0x21, 0x91, 0x8c),
+                (0x5e, 0xc9, 0x62),
+                (0xfd, 0xe7, 0x25),
+            ],
+            ColourMap::Magma => &[
+                (0x00, 0x00, 0x04),
+                (0x3b, 0x0f, 0x70),
+                (0x8c, 0x29, 0x81),
+                (0xde, 0x49, 0x68),
+                (0xfe, 0x9f, 0x6d),
+                (0xfc, 0xfd, 0xbf),
+            ],
+            ColourMap::Inferno => &[
+                (0x00, 0x00, 0x04),
+                (0x42, 0x0a, 0x68),
+                (0x93, 0x26, 0x67),
+                (0xdd, 0x51, 0x3a),
+                (0xfc, 0xa5, 0x0a),
+                (0xfc, 0xff, 0xa4),
+            ],
+            ColourMap::Greys => &[(0xf0, 0xf0, 0xf0), (0x00, 0x00, 0x00)],
+            ColourMap::Hot => &[
+                (0x00, 0x00, 0x00),
+                (0xff, 0x00, 0x00),
+                (0xff, 0xff, 0x00),
+                (0xff, 0xff, 0xff),
+            ],
+            ColourMap::Diverging => &[
+                (0x21, 0x66, 0xac),
+                (0x92, 0xc5, 0xde),
+                (0xf7, 0xf7, 0xf7),
+                (0xf4, 0xa5, 0x82),
+                (0xb2, 0x18, 0x2b),
+            ],
+        }
+    }
+
+    /// Colour of a value in [0, 1], values outside are clamped
+    pub fn colour(&self, value: f64) -> RGBColor {
+        let anchors = self.anchors();
+        let position = value.clamp(0.0, 1.0) * (anchors.len() - 1) as f64;
+        let i = (position.floor() as usize).min(anchors.len() - 2);
+        let t = position - i as f64;
+
+        let lerp = |a: u8, b: u8| (f64::from(a) + t * (f64::from(b) - f64::from(a))).round() as u8;
+        let (from, to) = (anchors[i], anchors[i + 1]);
+        RGBColor(lerp(from.0, to.0), lerp(from.1, to.1), lerp(from.2, to.2))
+    }
+}
+
+/// Qualitative palette telling categories apart (Tableau 10)
+const CATEGORIES: [(u8, u8, u8); 10] = [
+    (0x1f, 0x77, 0xb4),
+    (0xff, 0x7f, 0x0e),
+    (0x2c, 0xa0, 0x2c),
+    (0xd6, 0x27, 0x28),
+    (0x94, 0x67, 0xbd),
+    (0x8c, 0x56, 0x4b),
+    (0xe3, 0x77, 0xc2),
+    (0x7f, 0x7f, 0x7f),
+    (0xbc, 0xbd, 0x22),
+    (0x17, 0xbe, 0xcf),
+];
+
+/// Colour of the i-th category, cycling through the palette
+pub fn category_colour(i: usize) -> RGBColor {
+    let (r, g, b) = CATEGORIES[i % CATEGORIES.len()];
+    RGBColor(r, g, b)
+}
+
+/// Counts CGR points falling in each pixel of a `width` x `height` grid.
+///
+/// Pixels are stored row-major with row 0 at the top of the CGR square (y = 1),
+/// which for a 2^k square grid is the FCGR of the sequence.
+pub fn bin_points(points: &[(f64, f64)], width: usize, height: usize) -> Vec<u64> {
+    let mut grid = vec![0u64; width * height];
+    if width == 0 || height == 0 {
+        return grid;
+    }
+    for &(x, y) in points {
+        let col = (((x + 1.0) / 2.0 * width as f64) as usize).min(width - 1);
+        let row = (((1.0 - y) / 2.0 * height as f64) as usize).min(height - 1);
+        grid[row * width + col] += 1;
+    }
+    grid
+}
+
+/// Saves a grid of coloured cells, given row-major with `columns` cells per
+/// row, as a PNG where each cell is a square of pixels so that the image is at
+/// most `size` pixels wide and high. Cells take one pixel each when there are
+/// more cells than pixels, making the image larger than `size`.
+pub fn write_cells(
+    path: &Path,
+    colours: &[[u8; 3]],
+    columns: usize,
+    size: u32,
+) -> anyhow::Result<()> {
+    let rows = colours.len() / columns.max(1);
+    let scale = (size as usize / columns.max(rows).max(1)).max(1);
+    let mut buffer = Vec::with_capacity(colours.len() * scale * scale * 3);
+    for row in colours.chunks(columns.max(1)) {
+        let line: Vec<u8> = row.iter().flat_map(|colour| colour.repeat(scale)).collect();
+        for _ in 0..scale {
+            buffer.extend_from_slice(&line);
+        }
+    }
+    save_png(
+        path,
+        &buffer,
+        (columns * scale) as u32,
+        (rows * scale) as u32,
+        72,
+    )
+}
+
+/// Saves an RGB buffer as a PNG file recording its resolution
+pub fn save_png(
+    path: &Path,
+    buffer: &[u8],
+    width: u32,
+    height: u32,
+    dpi: u32,
+) -> anyhow::Result<()> {
+    let mut encoder = lodepng::Encoder::new();
+    encoder.info_raw_mut().colortype = lodepng::ColorType::RGB;
+    encoder.info_raw_mut().set_bitdepth(8);
+
+    // PNG stores the resolution in pixels per metre
+    let ppm = (f64::from(dpi) / 0.0254).round() as u32;
+    let info = encoder.info_png_mut();
+    info.phys_defined = true;
+    info.phys_x = ppm;
+    info.phys_y = ppm;
+    info.phys_unit = 1;
+
+    encoder.encode_file(path, buffer, width as usize, height as usize)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_point_logarithms() {
+        let one = 1u64 << LOG_BITS;
+        assert_eq!(log2_fixed(1), 0);
+        assert_eq!(log2_fixed(1024), 10 * one);
+        assert_eq!(log2_fixed(u64::MAX) >> LOG_BITS, 63);
+        assert!((log2_fixed(3) as f64 / one as f64 - 3f64.log2()).abs() < 1e-13);
+        assert_eq!(exp2_fixed(10 * one), 1024.0);
+        assert!((exp2_fixed(log2_fixed(1000)) - 1000.0).abs() < 1e-9);
+        assert_eq!(isqrt(99), 9);
+        assert_eq!(isqrt(u128::MAX), u128::from(u64::MAX));
+    }
+
+    #[test]
+    fn test_scale_normalise() {
+        assert_eq!(Scale::Linear.normalise(5, 10), 0.5);
+        assert_eq!(Scale::Log.normalise(10, 10), 1.0);
+        assert!(Scale::Log.normalise(1, 100) > Scale::Linear.normalise(1, 100));
+        assert_eq!(Scale::Log.normalise(0, 0), 0.0);
+    }
+
+    #[test]
+    fn test_scale_count_at_inverts_normalise() {
+        for scale in [Scale::Linear, Scale::Log] {
+            let value = scale.normalise(37, 1000);
+            assert!((scale.count_at(value, 1000) - 37.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_colour_map_ends() {
+        assert_eq!(ColourMap::Viridis.colour(0.0), RGBColor(0x44, 0x01, 0x54));
+        assert_eq!(ColourMap::Viridis.colour(1.0), RGBColor(0xfd, 0xe7, 0x25));
+        assert_eq!(ColourMap::Greys.colour(0.5), RGBColor(0x78, 0x78, 0x78));
+        assert_eq!(ColourMap::Hot.colour(2.0), RGBColor(0xff, 0xff, 0xff));
+        assert_eq!(ColourMap::Diverging.colour(0.5), RGBColor(0xf7, 0xf7, 0xf7));
+    }
+
+    #[test]
+    fn test_category_colour_cycles() {
+        assert_eq!(category_colour(0), RGBColor(0x1f, 0x77, 0xb4));
+        assert_eq!(category_colour(12), category_colour(2));
+    }
+
+    #[test]
+    fn test_bin_points_matches_fcgr_layout() {
+        // A, T, C and G corners of a 2 x 2 grid
+        let points = [
+            (0.5, 0.5),
+            (-0.5, 0.5),
+            (-0.5, -0.5),
+            (0.5, -0.5),
+            (1.0, 1.0),
+        ];
+        assert_eq!(bin_points(&points, 2, 2), vec![1, 2, 1, 1]);
+    }
+}
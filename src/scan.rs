@@ -0,0 +1,281 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::fmt;
+
+use noodles::fasta;
+use rayon::prelude::*;
+
+use crate::fcgr::Fcgr;
+
+/// Scale making the median absolute deviation a consistent estimator of the
+/// standard deviation of normally distributed values
+const MAD_SCALE: f64 = 1.4826;
+
+/// Window of a record with the distance of its signature to the genome signature
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    /// Record ID
+    pub seqid: String,
+
+    /// 0-based start position
+    pub start: usize,
+
+    /// 0-based end position, exclusive
+    pub end: usize,
+
+    /// Distance between the FCGR of the window and the whole-genome FCGR
+    pub distance: f64,
+}
+
+impl fmt::Display for Window {
+    /// Formats the window as a bedGraph line
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{:.6}",
+            self.seqid, self.start, self.end, self.distance
+        )
+    }
+}
+
+/// Windows of `window` bases every `step` bases along a sequence of length
+/// `len`, as (start, end) pairs.
+///
+/// A last window is aligned on the end of the sequence when the steps do not
+/// reach it, and sequences shorter than a window make a single window.
+pub fn windows(len: usize, window: usize, step: usize) -> Vec<(usize, usize)> {
+    if len <= window {
+        return vec![(0, len)];
+    }
+    let mut windows: Vec<(usize, usize)> = (0..=len - window)
+        .step_by(step.max(1))
+        .map(|start| (start, start + window))
+        .collect();
+    if windows.last().is_some_and(|&(_, end)| end < len) {
+        windows.push((len - window, len));
+    }
+    windows
+}
+
+/// Windows rolled by one task, sliding the k-mer counts from one window to
+/// the next
+const WINDOWS_PER_TASK: usize = 64;
+
+/// Maps the FCGR of each window of `window` bases every `step` bases along
+/// each record, with its record and bounds, in order.
+///
+/// Windows are rolled in parallel batches, so that each k-mer is counted
+/// once per batch rather than once per window.
+pub fn map_windows<T, F>(
+    records: &[fasta::Record],
+    k: u8,
+    window: usize,
+    step: usize,
+    f: F,
+) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&fasta::Record, usize, usize, Fcgr) -> T + Sync,
+{
+    let batches: Vec<(&fasta::Record, Vec<(usize, usize)>)> = records
+        .iter()
+        .flat_map(|record| {
+            windows(record.sequence().len(), window, step)
+                .chunks(WINDOWS_PER_TASK)
+                .map(|batch| (record, batch.to_vec()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    batches
+        .into_par_iter()
+        .flat_map_iter(|(record, batch)| {
+            Fcgr::windows(record.sequence().as_ref(), k, &batch)
+                .zip(&batch)
+                .map(|(fcgr, &(start, end))| f(record, start, end, fcgr))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Computes the distance of the FCGR of each window of the records to the
+/// FCGR of all the records together, counting k-mers on both strands when
+/// `canonical`
+pub fn scan(
+    records: &[fasta::Record],
+    k: u8,
+    window: usize,
+    step: usize,
+    canonical: bool,
+) -> Vec<Window> {
+    let signature = |fcgr: Fcgr| if canonical { fcgr.canonical() } else { fcgr };
+    let mut genome = Fcgr::new(k);
+    for record in records {
+        genome.add_sequence(record.sequence().as_ref());
+    }
+    let genome = signature(genome);
+
+    map_windows(records, k, window, step, |record, start, end, fcgr| {
+        Window {
+            seqid: record.name().to_string(),
+            start,
+            end,
+            distance: signature(fcgr).distance(&genome),
+        }
+    })
+}
+
+/// bedGraph intervals of the windows, which overlap when `step` is shorter
+/// than the windows: the distance of each window is given on a bin of `step`
+/// bases centred in the window.
+///
+/// Bins are cut where they would overlap the previous bin of the record, as
+/// for the last window aligned on the end of a record, and at the end of the
+/// record.
+pub fn bedgraph(windows: &[Window], step: usize) -> Vec<Window> {
+    windows
+        .chunk_by(|a, b| a.seqid == b.seqid)
+        .flat_map(|record| {
+            let len = record.last().map_or(0, |w| w.end);
+            let mut previous = 0;
+            record.iter().map(move |w| {
+                let centre = (w.start + w.end) / 2;
+                let start = centre.saturating_sub(step / 2).max(previous);
+                let end = (centre + step - step / 2).min(len).max(start);
+                previous = end;
+                Window {
+                    seqid: w.seqid.clone(),
+                    start,
+                    end,
+                    distance: w.distance,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Robust z-scores: deviations from the median in units of the scaled median
+/// absolute deviation (MAD).
+///
+/// When more than half of the values are equal the MAD is zero, and values
+/// away from the median get an infinite score.
+pub fn robust_z_scores(values: &[f64]) -> Vec<f64> {
+    let median = |mut v: Vec<f64>| -> f64 {
+        v.sort_by(|a, b| a.total_cmp(b));
+        let n = v.len();
+        match n {
+            0 => 0.0,
+            _ if n % 2 == 1 => v[n / 2],
+            _ => (v[n / 2 - 1] + v[n / 2]) / 2.0,
+        }
+    };
+
+    let centre = median(values.to_vec());
+    let mad = median(values.iter().map(|v| (v - centre).abs()).collect());
+    let scale = MAD_SCALE * mad;
+    values
+        .iter()
+        .map(|v| {
+            let deviation = v - centre;
+            if deviation == 0.0 {
+                0.0
+            } else {
+                deviation / scale
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::record;
+
+    #[test]
+    fn test_windows() {
+        assert_eq!(windows(10, 4, 3), vec![(0, 4), (3, 7), (6, 10)]);
+        assert_eq!(windows(11, 4, 3), vec![(0, 4), (3, 7), (6, 10), (7, 11)]);
+        assert_eq!(windows(3, 4, 3), vec![(0, 3)]);
+    }
+
+    #[test]
+    fn test_scan_finds_inserted_region() {
+        let mut sequence = b"ACGTTGCAAGCTTACG".repeat(20);
+        // Island of a different composition in the middle
+        sequence.splice(160..192, b"A".repeat(32));
+        let windows = scan(&[record("chr", &sequence)], 2, 32, 32, false);
+        assert_eq!(windows.len(), 10);
+
+        let z = robust_z_scores(&windows.iter().map(|w| w.distance).collect::<Vec<_>>());
+        let flagged: Vec<&Window> = windows
+            .iter()
+            .zip(&z)
+            .filter(|(_, &z)| z > 3.0)
+            .map(|(w, _)| w)
+            .collect();
+        assert_eq!(flagged.len(), 1);
+        assert_eq!((flagged[0].start, flagged[0].end), (160, 192));
+        assert!(flagged[0].to_string().starts_with("chr\t160\t192\t"));
+    }
+
+    #[test]
+    fn test_scan_canonical_ignores_orientation() {
+        let mut sequence = b"ACGGTTACAGGATTACCAGGAATTCGA".repeat(12);
+        sequence.splice(100..132, b"AAAAC".repeat(7)[..32].to_vec());
+        let reverse = crate::utils::reverse_complement(&sequence);
+
+        let distances = |sequence: &[u8], canonical| -> Vec<f64> {
+            scan(&[record("chr", sequence)], 3, 36, 36, canonical)
+                .iter()
+                .map(|w| w.distance)
+                .collect()
+        };
+        let forward = distances(&sequence, true);
+        let mut backward = distances(&reverse, true);
+        backward.reverse();
+        assert_eq!(forward.len(), 9);
+        for (f, b) in forward.iter().zip(&backward) {
+            assert!((f - b).abs() < 1e-12);
+        }
+        assert_ne!(distances(&sequence, false), forward);
+    }
+
+    #[test]
+    fn test_bedgraph_bins_do_not_overlap() {
+        let sequence = b"ACGTTGCAAGCTTACG".repeat(4);
+        let records = [record("chr1", &sequence), record("chr2", &sequence[..50])];
+        let windows = scan(&records, 2, 20, 8, false);
+        let bins = bedgraph(&windows, 8);
+        assert_eq!(bins.len(), windows.len());
+
+        let bounds: Vec<(&str, usize, usize)> = bins
+            .iter()
+            .map(|w| (w.seqid.as_str(), w.start, w.end))
+            .collect();
+        assert_eq!(&bounds[..2], &[("chr1", 6, 14), ("chr1", 14, 22)]);
+        // The last window of chr1, at 44..64, only adds what follows 54
+        assert_eq!(bounds[6], ("chr1", 54, 58));
+        assert_eq!(&bounds[7..9], &[("chr2", 6, 14), ("chr2", 14, 22)]);
+        assert_eq!(bounds.last(), Some(&("chr2", 38, 44)));
+        for pair in bins.windows(2) {
+            if pair[0].seqid == pair[1].seqid {
+                assert!(pair[0].end <= pair[1].start);
+            }
+        }
+        for (bin, window) in bins.iter().zip(&windows) {
+            assert_eq!(bin.distance, window.distance);
+            assert!(bin.start < bin.end);
+        }
+    }
+
+    #[test]
+    fn test_robust_z_scores() {
+        let z = robust_z_scores(&[1.0, 2.0, 3.0, 4.0, 100.0]);
+        assert_eq!(z[2], 0.0);
+        assert!((z[3] - 1.0 / MAD_SCALE).abs() < 1e-12);
+        assert!(z[4] > 3.0);
+        assert_eq!(robust_z_scores(&[]), Vec::<f64>::new());
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright 2021-2025 Anicet Ebou.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT)
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use noodles::fasta;
+use rayon::prelude::*;
+
+use crate::db::Database;
+use crate::fcgr::Fcgr;
+use crate::scan;
+
+/// Screening verdict of a contig
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Signature consistent with the rest of the assembly
+    Ok,
+
+    /// Signature far from the assembly signature
+    Outlier,
+
+    /// Nearest reference differs from the nearest reference of the assembly
+    Reference,
+
+    /// Contig too short to be screened reliably
+    Short,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Status::Ok => "ok",
+            Status::Outlier => "outlier",
+            Status::Reference => "reference",
+            Status::Short => "short",
+        })
+    }
+}
+
+/// Screening result of a single contig
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contig {
+    /// Contig ID
+    pub id: String,
+
+    /// Number of nucleotides
+    pub length: usize,
+
+    /// Fraction of G and C among A, C, G and T (or U)
+    pub gc: f64,
+
+    /// Distance between the contig FCGR and the assembly FCGR
+    pub distance: f64,
+
+    /// Robust z-score of the distance among the screened contigs
+    pub z_score: f64,
+
+    /// Nearest database reference and its distance to the contig
+    pub nearest: Option<(String, f64)>,
+
+    pub status: Status,
+}
+
+impl fmt::Display for Contig {
+    /// Formats the contig as a TSV line, missing values written `.`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\t{}\t{:.4}\t", self.id, self.length, self.gc)?;
+        if self.status == Status::Short {
+            write!(f, ".\t.\t")?;
+        } else {
+            write!(f, "{:.6}\t{:.2}\t", self.distance, self.z_score)?;
+        }
+        match &self.nearest {
+            Some((reference, distance)) => write!(f, "{reference}\t{distance:.6}\t")?,
+            None => write!(f, ".\t.\t")?,
+        }
+        write!(f, "{}", self.status)
+    }
+}
+
+/// Screening of the contigs of an assembly for foreign sequences
+#[derive(Debug)]
+pub struct Screen {
+    /// Nearest database reference of the whole assembly
+    pub assembly_reference: Option<String>,
+
+    pub contigs: Vec<Contig>,
+}
+
+impl Screen {
+    /// Compares the FCGR of each contig with the FCGR of the whole assembly
+    /// and, when given, with the references of a database.
+    ///
+    /// The assembly FCGR counts the k-mers of all contigs, so each contig
+    /// weighs in proportion to its length. Contigs shorter than `min_length`
+    /// are reported but left out of the distance statistics. A contig is an
+    /// outlier when the robust z-score of its distance exceeds `z_threshold`,
+    /// and is flagged otherwise when its nearest reference is not the nearest
+    /// reference of the assembly.
+    pub fn new(
+        records: &[fasta::Record],
+        k: u8,
+        min_length: usize,
+        z_threshold: f64,
+        database: Option<&Database>,
+    ) -> Self {
+        let signatures: Vec<Fcgr> = records
+            .par_iter()
+            .map(|record| Fcgr::from_sequence(record.sequence().as_ref(), k))
+            .collect();
+        let mut assembly = Fcgr::new(k);
+        for record in records {
+            assembly.add_sequence(record.sequence().as_ref());
+        }
+
+        let nearest = |signature: &Fcgr| {
+            database.and_then(|db| {
+                db.nearest(signature, 1)
+                    .first()
+                    .map(|&(i, distance)| (db.entries[i].id.clone(), distance))
+            })
+        };
+        let assembly_reference = nearest(&assembly).map(|(id, _)| id);
+
+        let mut contigs: Vec<Contig> = records
+            .par_iter()
+            .zip(&signatures)
+            .map(|(record, signature)| {
+                let sequence: &[u8] = record.sequence().as_ref();
+                let short = sequence.len() < min_length;
+                Contig {
+                    id: record.name().to_string(),
+                    length: sequence.len(),
+                    gc: gc_content(sequence),
+                    distance: signature.distance(&assembly),
+                    z_score: 0.0,
+                    nearest: if short { None } else { nearest(signature) },
+                    status: if short { Status::Short } else { Status::Ok },
+                }
+            })
+            .collect();
+
+        let screened: Vec<usize> = (0..contigs.len())
+            .filter(|&i| contigs[i].status != Status::Short)
+            .collect();
+        let distances: Vec<f64> = screened.iter().map(|&i| contigs[i].distance).collect();
+        for (&i, z) in screened.iter().zip(scan::robust_z_scores(&distances)) {
+            let contig = &mut contigs[i];
+            contig.z_score = z;
+            if z > z_threshold {
+                contig.status = Status::Outlier;
+            } else if let (Some((reference, _)), Some(expected)) =
+                (&contig.nearest, &assembly_reference)
+            {
+                if reference != expected {
+                    contig.status = Status::Reference;
+                }
+            }
+        }
+
+        Screen {
+            assembly_reference,
+            contigs,
+        }
+    }
+
+    /// Writes the screened contigs as TSV, only the flagged ones unless `all`
+    pub fn write_all<W: Write>(&self, mut writer: W, all: bool) -> io::Result<()> {
+        if let Some(reference) = &self.assembly_reference {
+            writeln!(writer, "#assembly_reference={reference}")?;
+        }
+        writeln!(
+            writer,
+            "#contig\tlength\tgc\tdistance\tz_score\tnearest_reference\treference_distance\tstatus"
+        )?;
+        for contig in &self.contigs {
+            if all || matches!(contig.status, Status::Outlier | Status::Reference) {
+                writeln!(writer, "{contig}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn gc_content(sequence: &[u8]) -> f64 {
+    let (gc, acgt) = sequence.iter().fold((0usize, 0usize), |(gc, acgt), b| {
+        match b.to_ascii_uppercase() {
+            b'G' | b'C' => (gc + 1, acgt + 1),
+            b'A' | b'T' | b'U' => (gc, acgt + 1),
+            _ => (gc, acgt),
+        }
+    });
+    gc as f64 / acgt.max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::record;
+
+    fn assembly() -> Vec<fasta::Record> {
+        let mut records: Vec<fasta::Record> = (0..8)
+            .map(|i| {
+                let mut sequence = b"ACGTTGCAAGCTTACG".repeat(10);
+                sequence.rotate_left(i);
+                record(&format!("contig{i}"), &sequence)
+            })
+            .collect();
+        records.push(record("foreign", &b"AAAAAAAAAC".repeat(16)));
+        records.push(record("tiny", b"GGGGGGGG"));
+        records
+    }
+
+    #[test]
+    fn test_screen_flags_foreign_contig() {
+        let screen = Screen::new(&assembly(), 2, 50, 3.5, None);
+        let status: Vec<Status> = screen.contigs.iter().map(|c| c.status).collect();
+        assert_eq!(&status[..8], &[Status::Ok; 8]);
+        assert_eq!(status[8], Status::Outlier);
+        assert_eq!(status[9], Status::Short);
+        assert!((screen.contigs[8].gc - 0.1).abs() < 1e-12);
+
+        let mut output = Vec::new();
+        screen.write_all(&mut output, false).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("foreign\t160\t0.1000\t"));
+        assert!(lines[1].ends_with("\t.\t.\toutlier"));
+
+        let mut output = Vec::new();
+        screen.write_all(&mut output, true).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 11);
+        assert_eq!(
+            output.lines().last(),
+            Some("tiny\t8\t1.0000\t.\t.\t.\t.\tshort")
+        );
+    }
+
+    #[test]
+    fn test_gc_content() {
+        assert_eq!(gc_content(b"ACGTNN"), 0.5);
+        assert_eq!(gc_content(b""), 0.0);
+    }
+}
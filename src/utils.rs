@@ -3,13 +3,17 @@
 // This file may not be copied, modified, or distributed except according
 // to those terms.
 
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::{Path, PathBuf};
 
+use anyhow::Context;
 use dssim_core::*;
 use imgref::*;
+use itertools::Itertools;
 use load_image::*;
+use noodles::fasta;
 
 // Copied https://github.com/kornelski/dssim/blob/f3e2191efed786081f780ddea08a1e6027f31680/src/lib.rs#L10
 /// Load PNG or JPEG image from the given path. Applies color profiles and converts to sRGB.
@@ -79,6 +83,94 @@ where
     Ok(io::BufReader::new(file).lines())
 }
 
+/// Reads all the records of a FASTA file
+pub fn read_records<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<fasta::Record>> {
+    let file = File::open(path.as_ref())
+        .with_context(|| format!("Failed to open {}", path.as_ref().display()))?;
+    let mut reader = fasta::Reader::new(io::BufReader::new(file));
+    Ok(reader.records().collect::<io::Result<Vec<_>>>()?)
+}
+
+/// Reverse complement of a nucleotide sequence, IUPAC codes included.
+///
+/// Case is kept, U is complemented to A and other symbols are left as is.
+pub fn reverse_complement(sequence: &[u8]) -> Vec<u8> {
+    sequence
+        .iter()
+        .rev()
+        .map(|&b| {
+            let complement = match b.to_ascii_uppercase() {
+                b'A' => b'T',
+                b'T' | b'U' => b'A',
+                b'C' => b'G',
+                b'G' => b'C',
+                b'R' => b'Y',
+                b'Y' => b'R',
+                b'K' => b'M',
+                b'M' => b'K',
+                b'B' => b'V',
+                b'V' => b'B',
+                b'D' => b'H',
+                b'H' => b'D',
+                other => other,
+            };
+            if b.is_ascii_lowercase() {
+                complement.to_ascii_lowercase()
+            } else {
+                complement
+            }
+        })
+        .collect()
+}
+
+/// Collects sequence files given either directly or in a file listing one path per line
+pub fn sequence_files(
+    file: Option<&PathBuf>,
+    list: Option<&PathBuf>,
+    flag: &str,
+) -> anyhow::Result<Vec<String>> {
+    match (file, list) {
+        (Some(f), _) => Ok(vec![f.to_string_lossy().into_owned()]),
+        (None, Some(l)) => Ok(read_lines(l)?.map_while(Result::ok).collect()),
+        (None, None) => anyhow::bail!("Missing {flag} file"),
+    }
+}
+
+/// List the genomes to compare and the pairs of indices to score.
+///
+/// Each genome appears once in the returned list so that its representation
+/// can be computed a single time. Self pairs and symmetric duplicates are skipped.
+pub fn comparison_pairs(
+    queries: Vec<String>,
+    references: Vec<String>,
+    allvsall: bool,
+) -> (Vec<String>, Vec<(usize, usize)>) {
+    let mut genomes: Vec<String> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut index_of = |genome: String| -> usize {
+        *positions.entry(genome.clone()).or_insert_with(|| {
+            genomes.push(genome);
+            genomes.len() - 1
+        })
+    };
+
+    let qidx: Vec<usize> = queries.into_iter().map(&mut index_of).collect();
+    let ridx: Vec<usize> = references.into_iter().map(&mut index_of).collect();
+
+    let pairs = if allvsall {
+        (0..genomes.len()).tuple_combinations().collect()
+    } else {
+        let mut seen = HashSet::new();
+        qidx.iter()
+            .cartesian_product(&ridx)
+            .filter(|&(q, r)| q != r && seen.insert((*q.min(r), *q.max(r))))
+            .map(|(&q, &r)| (q, r))
+            .collect()
+    };
+
+    (genomes, pairs)
+}
+
 /// Compare image dimensions
 pub fn is_same_width_height(
     img1: &(DssimImage<f32>, String),
@@ -101,6 +193,20 @@ pub fn eimgprint(img1: &(DssimImage<f32>, String), img2: &(DssimImage<f32>, Stri
 }
 
 // Tests -------------------------------------------
+/// Helpers shared by the tests of several modules
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use noodles::fasta;
+
+    /// FASTA record of the given name and sequence
+    pub(crate) fn record(name: &str, sequence: &[u8]) -> fasta::Record {
+        fasta::Record::new(
+            fasta::record::Definition::new(name, None),
+            fasta::record::Sequence::from(sequence.to_vec()),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -109,6 +215,14 @@ mod tests {
     use std::path::PathBuf;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_reverse_complement() {
+        assert_eq!(reverse_complement(b"ACGTN"), b"NACGT");
+        assert_eq!(reverse_complement(b"acgU"), b"Acgt");
+        assert_eq!(reverse_complement(b"RYKMBVDHSW"), b"WSDHBVKMRY");
+        assert_eq!(reverse_complement(b""), b"");
+    }
+
     #[test]
     fn test_load_image() {
         let attr = Dssim::new();
@@ -215,6 +329,36 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_read_records() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("seqs.fa");
+        std::fs::write(&file_path, ">s1 first\nACGT\n>s2\nGG\n").unwrap();
+
+        let records = read_records(&file_path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].name(), "s2");
+        assert_eq!(records[1].sequence().as_ref(), b"GG");
+    }
+
+    #[test]
+    fn test_comparison_pairs_cartesian() {
+        let queries = vec!["a.fa".to_string(), "b.fa".to_string()];
+        let references = vec!["b.fa".to_string(), "a.fa".to_string(), "c.fa".to_string()];
+        let (genomes, pairs) = comparison_pairs(queries, references, false);
+        assert_eq!(genomes, vec!["a.fa", "b.fa", "c.fa"]);
+        assert_eq!(pairs, vec![(0, 1), (0, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn test_comparison_pairs_allvsall() {
+        let queries = vec!["a.fa".to_string()];
+        let references = vec!["b.fa".to_string(), "a.fa".to_string(), "c.fa".to_string()];
+        let (genomes, pairs) = comparison_pairs(queries, references, true);
+        assert_eq!(genomes.len(), 3);
+        assert_eq!(pairs, vec![(0, 1), (0, 2), (1, 2)]);
+    }
+
     #[test]
     fn test_is_same_width_height_true() {
         let attr = Dssim::new();